@@ -0,0 +1,62 @@
+use std::env;
+use std::io::{self, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use rustcolorizer::error::{RczError, Result};
+
+// Prints definitions for `shell`, or the shell $SHELL names, that run each of
+// the configured commands that is installed through rcz.
+//
+// Definitions call rcz with `command rcz` (`^rcz` in nushell) so that they
+// cannot pick up a function or alias of the same name, and rcz starts the
+// wrapped command itself rather than through the shell, so a definition
+// named after the command it wraps never calls itself. rcz is never wrapped.
+pub fn generate_aliases(shell: Option<&str>, commands: &[String]) -> Result<()> {
+    let shell = match shell {
+        Some(shell) => shell.to_string(),
+        None => detect_shell(),
+    };
+
+    let mut out = io::stdout().lock();
+    let written = writeln!(out, "# rcz aliases for {}, generated by `rcz --aliases {}`", shell, shell).and_then(|_| {
+        commands
+            .iter()
+            .filter(|command| *command != "rcz" && on_path(command))
+            .try_for_each(|command| writeln!(out, "{}", alias_for_shell(command, &shell)))
+    });
+    match written {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(RczError::io("stdout", e)),
+        _ => Ok(()),
+    }
+}
+
+fn detect_shell() -> String {
+    let shell = env::var("SHELL").unwrap_or_default();
+    let name = Path::new(&shell).file_name().and_then(|n| n.to_str()).unwrap_or("");
+    match name {
+        "zsh" | "fish" => name.to_string(),
+        "nu" => "nushell".to_string(),
+        _ => "bash".to_string(),
+    }
+}
+
+fn alias_for_shell(command: &str, shell: &str) -> String {
+    match shell {
+        "fish" => format!("function {0} --wraps {0}; command rcz {0} $argv; end", command),
+        "nushell" => format!("alias {0} = ^rcz {0}", command),
+        _ => format!("alias {0}='command rcz {0}'", command),
+    }
+}
+
+// Whether an executable of this name is found on $PATH.
+fn on_path(command: &str) -> bool {
+    let Some(path) = env::var_os("PATH") else {
+        return false;
+    };
+    env::split_paths(&path).any(|dir| {
+        dir.join(command)
+            .metadata()
+            .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    })
+}
@@ -0,0 +1,37 @@
+// Profiles compiled into the binary, so that a fresh install colorizes
+// common commands without any configuration on disk. They are addressed as
+// `builtin:<name>` wherever a configuration path is expected.
+
+pub const BUILTIN_PREFIX: &str = "builtin:";
+
+pub const BUILTIN_MAIN_CONFIG: &str = "builtin:rcz.conf";
+
+pub static BUILTIN_FILES: phf::Map<&'static str, &'static str> = phf::phf_map! {
+    "conf.cargo" => include_str!("../profiles/conf.cargo"),
+    "conf.df" => include_str!("../profiles/conf.df"),
+    "conf.diff" => include_str!("../profiles/conf.diff"),
+    "conf.dig" => include_str!("../profiles/conf.dig"),
+    "conf.dockerps" => include_str!("../profiles/conf.dockerps"),
+    "conf.gcc" => include_str!("../profiles/conf.gcc"),
+    "conf.ip" => include_str!("../profiles/conf.ip"),
+    "conf.journalctl" => include_str!("../profiles/conf.journalctl"),
+    "conf.kubectl" => include_str!("../profiles/conf.kubectl"),
+    "conf.make" => include_str!("../profiles/conf.make"),
+    "conf.mount" => include_str!("../profiles/conf.mount"),
+    "conf.netstat" => include_str!("../profiles/conf.netstat"),
+    "conf.ping" => include_str!("../profiles/conf.ping"),
+    "conf.ps" => include_str!("../profiles/conf.ps"),
+    "conf.syslog" => include_str!("../profiles/conf.syslog"),
+    "conf.traceroute" => include_str!("../profiles/conf.traceroute"),
+    "rcz.conf" => include_str!("../profiles/rcz.conf"),
+};
+
+// Returns the contents of a `builtin:<name>` path, if it names an embedded file.
+pub fn builtin_file(path: &str) -> Option<&'static str> {
+    BUILTIN_FILES.get(path.strip_prefix(BUILTIN_PREFIX)?).copied()
+}
+
+// Returns the `builtin:<name>` path for a profile name, if one is embedded.
+pub fn builtin_profile(name: &str) -> Option<String> {
+    BUILTIN_FILES.contains_key(name).then(|| format!("{}{}", BUILTIN_PREFIX, name))
+}
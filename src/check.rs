@@ -0,0 +1,245 @@
+use fancy_regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::builtin;
+use crate::config;
+use crate::data::CgrcCountMode;
+use crate::error::RczError;
+use crate::rczat::{self, MAX_RULE_LINES};
+
+// Lints the main configurations and every profile they name, each profile
+// once, and returns all the problems found rather than stopping at the first.
+pub fn check_main_configs(main_configs: &[PathBuf]) -> Vec<RczError> {
+    let mut problems = Vec::new();
+    let mut profiles: Vec<PathBuf> = Vec::new();
+
+    for main_config in main_configs {
+        let config_path = main_config.to_string_lossy();
+        let entries = match config::read_main_config(&config_path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                problems.push(e);
+                continue;
+            }
+        };
+        for entry in entries {
+            if let Err(e) = regex::Regex::new(&entry.pattern) {
+                problems.push(RczError::regex(&config_path, entry.line, e));
+            }
+            match config::resolve_profile(&entry.profile, main_config) {
+                Some(profile) if !profiles.contains(&profile) => profiles.push(profile),
+                Some(_) => {}
+                None => problems.push(RczError::config(
+                    &config_path,
+                    entry.line,
+                    format!("profile `{}` not found", entry.profile),
+                )),
+            }
+        }
+    }
+
+    for profile in profiles {
+        problems.extend(check_profile(&profile.to_string_lossy()));
+    }
+    problems
+}
+
+// A rule of the profile being checked, with where its keys were.
+#[derive(Default)]
+struct Rule {
+    line: usize,
+    has_regexp: bool,
+    regex: Option<Regex>,
+    colours: Option<(usize, usize)>,
+    // The groups `colours.GROUP=`, `values.GROUP=` and `gradient.GROUP=`
+    // lines are for, with their lines and keys.
+    group_keys: Vec<(usize, String, String)>,
+    stops: bool,
+}
+
+// Lints one profile.
+pub fn check_profile(path: &str) -> Vec<RczError> {
+    let contents = match builtin::builtin_file(path) {
+        Some(contents) => contents.to_string(),
+        None => match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => return vec![RczError::io(path, e)],
+        },
+    };
+
+    let mut problems = Vec::new();
+    let mut rules: Vec<Rule> = Vec::new();
+    let mut rule = Rule::default();
+    let mut started = false;
+
+    for (index, line) in contents.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line == "-" {
+            finish_rule(path, std::mem::take(&mut rule), &mut rules, &mut problems);
+            started = false;
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            problems.push(RczError::config(path, line_no, format!("expected KEY=VALUE, not `{}`", line)));
+            continue;
+        };
+        if !started {
+            rule.line = line_no;
+            started = true;
+        }
+
+        match key {
+            "regexp" => {
+                if rule.has_regexp {
+                    finish_rule(path, std::mem::take(&mut rule), &mut rules, &mut problems);
+                    rule.line = line_no;
+                }
+                rule.has_regexp = true;
+                match Regex::new(value) {
+                    Ok(regex) => rule.regex = Some(regex),
+                    Err(e) => problems.push(RczError::regex(path, line_no, e)),
+                }
+            }
+            "colours" => {
+                for token in rczat::unknown_color_tokens(value) {
+                    problems.push(RczError::config(path, line_no, format!("unknown colour or attribute `{}`", token)));
+                }
+                rule.colours = Some((line_no, rczat::color_count(value)));
+            }
+            _ if key.starts_with("colours.") || key.starts_with("values.") || key.starts_with("gradient.") => {
+                let (key, group) = key.split_once('.').unwrap_or_default();
+                let colours = match key {
+                    "gradient" => {
+                        if let Err(message) = rczat::parse_gradient(value) {
+                            problems.push(RczError::config(path, line_no, message));
+                        }
+                        Vec::new()
+                    }
+                    "values" => match rczat::split_value_entries(value) {
+                        Ok(entries) => entries.into_iter().map(|(_, colour)| colour).collect(),
+                        Err(message) => {
+                            problems.push(RczError::config(path, line_no, message));
+                            Vec::new()
+                        }
+                    },
+                    _ => {
+                        if let Err(message) = rczat::single_color(group, value) {
+                            problems.push(RczError::config(path, line_no, message));
+                        }
+                        vec![value]
+                    }
+                };
+                for token in colours.into_iter().flat_map(rczat::unknown_color_tokens) {
+                    problems.push(RczError::config(path, line_no, format!("unknown colour or attribute `{}`", token)));
+                }
+                if rule.has_regexp {
+                    rule.group_keys.push((line_no, key.to_string(), group.to_string()));
+                } else {
+                    let message = format!("`{}.{}` comes before the rule's regexp", key, group);
+                    problems.push(RczError::config(path, line_no, message));
+                }
+            }
+            "count" => match rczat::parse_count_mode(value) {
+                Some(mode) => rule.stops |= mode == CgrcCountMode::CgrcCountStop,
+                None => problems.push(RczError::config(path, line_no, format!("invalid count mode `{}`", value))),
+            },
+            "skip" => match value {
+                "yes" => rule.stops = true,
+                "no" => {}
+                _ => problems.push(RczError::config(path, line_no, format!("skip must be yes or no, not `{}`", value))),
+            },
+            "lines" => {
+                if !value.trim().parse().is_ok_and(|n: usize| (1..=MAX_RULE_LINES).contains(&n)) {
+                    problems.push(RczError::config(
+                        path,
+                        line_no,
+                        format!("`lines` must be from 1 to {}, not `{}`", MAX_RULE_LINES, value),
+                    ));
+                }
+            }
+            _ => problems.push(RczError::config(path, line_no, format!("unknown key `{}`", key))),
+        }
+    }
+    finish_rule(path, rule, &mut rules, &mut problems);
+
+    check_reachability(path, &rules, &mut problems);
+    // Some problems are only found once a rule or the whole profile has been
+    // read, so they are put back in line order.
+    problems.sort_by_key(|problem| match problem {
+        RczError::Regex { line, .. } | RczError::Config { line, .. } => *line,
+        _ => 0,
+    });
+    problems
+}
+
+fn finish_rule(path: &str, rule: Rule, rules: &mut Vec<Rule>, problems: &mut Vec<RczError>) {
+    if rule.line > 0 && !rule.has_regexp {
+        problems.push(RczError::config(path, rule.line, "rule has no regexp"));
+    }
+    let Some(regex) = &rule.regex else { return };
+    for (line, key, group) in &rule.group_keys {
+        if let Err(message) = rczat::group_index(Some(regex), key, group) {
+            problems.push(RczError::config(path, *line, message));
+        }
+    }
+    // Group 0, the whole match, takes the first colour.
+    if let Some((line, colours)) = rule.colours {
+        let groups = regex.captures_len();
+        if colours > groups {
+            problems.push(RczError::config(
+                path,
+                line,
+                format!("{} colours but the regexp only has {} groups counting the whole match", colours, groups),
+            ));
+        }
+    }
+    rules.push(rule);
+}
+
+// Rules after a stopping rule that matches every line are never tried, and
+// a rule with the same regexp as an earlier stopping rule never matches.
+fn check_reachability(path: &str, rules: &[Rule], problems: &mut Vec<RczError>) {
+    for (index, rule) in rules.iter().enumerate() {
+        let Some(regex) = rule.regex.as_ref().filter(|_| rule.stops) else { continue };
+        if matches_every_line(regex) {
+            if let Some(next) = rules.get(index + 1) {
+                problems.push(RczError::config(
+                    path,
+                    next.line,
+                    format!("unreachable: the rule at line {} matches every line and stops", rule.line),
+                ));
+            }
+            return;
+        }
+        for later in &rules[index + 1..] {
+            if later.regex.as_ref().is_some_and(|r| r.as_str() == regex.as_str()) {
+                problems.push(RczError::config(
+                    path,
+                    later.line,
+                    format!("unreachable: the rule at line {} has the same regexp and stops", rule.line),
+                ));
+            }
+        }
+    }
+}
+
+// A regexp that finds a match in the empty line and in unrelated text is
+// taken to match anywhere.
+fn matches_every_line(regex: &Regex) -> bool {
+    ["", "x", " \t#!%~ 9"].iter().all(|line| regex.is_match(line).unwrap_or(false))
+}
+
+// Whether a path names a main configuration rather than a profile: main
+// configurations map patterns to profiles and have no regexp= keys.
+pub fn is_main_config(path: &Path) -> bool {
+    let path_str = path.to_string_lossy();
+    let contents = match builtin::builtin_file(&path_str) {
+        Some(contents) => contents.to_string(),
+        None => fs::read_to_string(path).unwrap_or_default(),
+    };
+    !contents.lines().any(|line| line.trim_start().starts_with("regexp="))
+}
@@ -1,54 +1,428 @@
+use log::{debug, trace};
+use std::env;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use clap::builder::Str;
+use std::path::{Path, PathBuf};
+use crate::builtin;
+use crate::error::{RczError, Result};
 
-pub fn extract_commands_from_config(config_path: &str) -> Vec<String> {
-    let file = File::open(config_path).unwrap();
-    let reader = BufReader::new(file);
-    let mut commands = Vec::new();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColourMode {
+    On,
+    Off,
+    Auto,
+}
+
+// Decides whether a stream gets colorized. In auto mode FORCE_COLOR wins,
+// then NO_COLOR and TERM=dumb turn colours off, and otherwise we only
+// colorize when the stream is a terminal.
+pub fn use_colour(mode: ColourMode, is_terminal: bool) -> bool {
+    let env_value = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+    match mode {
+        ColourMode::On => true,
+        ColourMode::Off => false,
+        ColourMode::Auto => {
+            if env_value("FORCE_COLOR").is_some_and(|v| v != "0") {
+                true
+            } else if env_value("NO_COLOR").is_some() || env_value("TERM").as_deref() == Some("dumb") {
+                false
+            } else {
+                is_terminal
+            }
+        }
+    }
+}
+
+// One `pattern=profile` entry of a main configuration, with the line it is on.
+pub struct MainConfigEntry {
+    pub line: usize,
+    pub pattern: String,
+    pub profile: String,
+}
+
+// Reads the entries of a main configuration. Besides our `pattern=profile`
+// lines this accepts grc.conf's format, where a line holding the pattern is
+// followed by a line holding the profile.
+pub fn read_main_config(config_path: &str) -> Result<Vec<MainConfigEntry>> {
+    if let Some(contents) = builtin::builtin_file(config_path) {
+        return parse_main_config(contents.as_bytes(), config_path);
+    }
+    let file = File::open(config_path).map_err(|e| RczError::io(config_path, e))?;
+    parse_main_config(BufReader::new(file), config_path)
+}
+
+// The format is decided once for the whole file: grc's profile lines never
+// hold an `=`, while every line of ours does. Patterns may contain `=`
+// themselves, so our lines are split at their last one.
+fn parse_main_config<R: BufRead>(reader: R, config_path: &str) -> Result<Vec<MainConfigEntry>> {
+    let mut lines = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| RczError::io(config_path, e))?.trim().to_string();
+        if !line.starts_with("#") && !line.is_empty() {
+            lines.push((index + 1, line));
+        }
+    }
+
+    let entries = if lines.iter().any(|(_, line)| !line.contains('=')) {
+        lines
+            .chunks_exact(2)
+            .map(|pair| MainConfigEntry { line: pair[0].0, pattern: pair[0].1.clone(), profile: pair[1].1.clone() })
+            .collect()
+    } else {
+        lines
+            .iter()
+            .filter_map(|(line_no, line)| {
+                let (pattern, profile) = line.rsplit_once('=')?;
+                Some(MainConfigEntry {
+                    line: *line_no,
+                    pattern: pattern.trim().to_string(),
+                    profile: profile.trim().to_string(),
+                })
+            })
+            .collect()
+    };
+
+    Ok(entries)
+}
+
+pub fn extract_commands_from_config(config_path: &str) -> Result<Vec<String>> {
+    Ok(read_main_config(config_path)?.into_iter().map(|entry| entry.pattern).collect())
+}
+
+// Upper bound on the names a single pattern expands to.
+const MAX_COMMAND_NAMES: usize = 32;
+
+// Command names a main config pattern can match, such as `ping` and `ping6`
+// for `^ping6?\b`. Only the literal head of the pattern is expanded, with its
+// alternations and optional parts; a head using any other regex construct
+// yields no names.
+pub fn command_names(pattern: &str) -> Vec<String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut names: Vec<String> = Vec::new();
+    for alternative in split_alternatives(&chars) {
+        let head = strip_anchor(alternative);
+        for name in expand_sequence(head, false).unwrap_or_default() {
+            if !name.is_empty() && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+// Names of all commands the main configurations in use have patterns for,
+// in configuration order and without duplicates.
+pub fn configured_commands(config: Option<&Path>) -> Result<Vec<String>> {
+    let mut commands: Vec<String> = Vec::new();
+    for config_path in main_config_files(config) {
+        for entry in read_main_config(&config_path.to_string_lossy())? {
+            for name in command_names(&entry.pattern) {
+                if !commands.contains(&name) {
+                    commands.push(name);
+                }
+            }
+        }
+    }
+    Ok(commands)
+}
+
+// Drops a leading `^`, or grc's `(^|/)` style prefix that also lets the
+// command be given as a path.
+fn strip_anchor(chars: &[char]) -> &[char] {
+    if chars.first() == Some(&'^') {
+        return &chars[1..];
+    }
+    if chars.starts_with(&['(', '^', '|']) {
+        if let Some(end) = group_end(chars, 0) {
+            return &chars[end + 1..];
+        }
+    }
+    chars
+}
+
+// Splits on the `|`s that are not inside a group or a character class.
+fn split_alternatives(chars: &[char]) -> Vec<&[char]> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start, mut i) = (0usize, 0, 0);
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '[' => i = class_end(chars, i).unwrap_or(chars.len()),
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            '|' if depth == 0 => {
+                parts.push(&chars[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    parts.push(&chars[start..]);
+    parts
+}
+
+fn group_end(chars: &[char], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = open;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '[' => i = class_end(chars, i)?,
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
 
-    for line in reader.lines() {
-        let line = line.unwrap().trim().to_string();
-        if line.starts_with("#") || line.is_empty() {
-            continue;
+fn class_end(chars: &[char], open: usize) -> Option<usize> {
+    let mut i = open + 1;
+    // A `]` right after the opening bracket is a literal.
+    if chars.get(i) == Some(&'^') {
+        i += 1;
+    }
+    if chars.get(i) == Some(&']') {
+        i += 1;
+    }
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            ']' => return Some(i),
+            _ => {}
         }
+        i += 1;
+    }
+    None
+}
 
-        let parts: Vec<&str> = line.split('=').collect();
-        if parts.len() == 2 {
-            let command = parts[0].trim();
-            commands.push(command.to_string());
+// Expands a sequence of literals, groups and optional parts into the strings
+// it matches. At the top level the sequence ends at a word boundary, space or
+// end anchor; inside a group (`whole`) it must be expanded entirely.
+fn expand_sequence(chars: &[char], whole: bool) -> Option<Vec<String>> {
+    let mut results = vec![String::new()];
+    let mut i = 0;
+    while i < chars.len() {
+        let item = match chars[i] {
+            '(' => {
+                let end = group_end(chars, i)?;
+                let mut inner = &chars[i + 1..end];
+                if inner.starts_with(&['?', ':']) {
+                    inner = &inner[2..];
+                }
+                i = end + 1;
+                split_alternatives(inner)
+                    .into_iter()
+                    .map(|alternative| expand_sequence(alternative, true))
+                    .collect::<Option<Vec<_>>>()
+                    .map(|alternatives| alternatives.concat())
+            }
+            '\\' => {
+                let escaped = *chars.get(i + 1)?;
+                i += 2;
+                if escaped == 'b' || escaped == 's' {
+                    if whole {
+                        return None;
+                    }
+                    break;
+                }
+                (escaped.is_ascii_punctuation()).then(|| vec![escaped.to_string()])
+            }
+            ' ' | '$' => {
+                if whole {
+                    return None;
+                }
+                break;
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '-' => {
+                i += 1;
+                Some(vec![c.to_string()])
+            }
+            _ => return None,
+        };
+
+        let item = match chars.get(i) {
+            Some('?') => {
+                i += 1;
+                let mut options = vec![String::new()];
+                options.extend(item.unwrap_or_default());
+                options
+            }
+            Some('*' | '+' | '{') => return None,
+            _ => item?,
+        };
+
+        results = results
+            .iter()
+            .flat_map(|prefix| item.iter().map(move |suffix| format!("{}{}", prefix, suffix)))
+            .collect();
+        if results.len() > MAX_COMMAND_NAMES {
+            return None;
         }
     }
+    Some(results)
+}
+
+// Directories searched for the main configuration and for profiles, most
+// specific first. $RCZ_PATH, a list of directories like $PATH, replaces them.
+pub fn config_dirs() -> Vec<PathBuf> {
+    if let Some(dirs) = search_path() {
+        return dirs;
+    }
+    let home = env::var_os("HOME").filter(|h| !h.is_empty()).map(PathBuf::from);
+    let xdg = env::var_os("XDG_CONFIG_HOME")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|h| h.join(".config")));
+
+    let mut dirs = Vec::new();
+    if let Some(xdg) = xdg {
+        dirs.push(xdg.join("rcz"));
+    }
+    if let Some(home) = &home {
+        dirs.push(home.join(".rcz"));
+    }
+    dirs.push(PathBuf::from("/usr/local/share/rcz"));
+    dirs.push(PathBuf::from("/usr/share/rcz"));
+    dirs
+}
 
-    commands
+// grc's directories, searched after ours so that existing grc profiles work.
+// With $RCZ_PATH only the directories it lists are searched.
+fn grc_dirs() -> Vec<PathBuf> {
+    if search_path().is_some() {
+        return Vec::new();
+    }
+    let mut dirs = Vec::new();
+    if let Some(home) = env::var_os("HOME").filter(|h| !h.is_empty()) {
+        dirs.push(PathBuf::from(home).join(".grc"));
+    }
+    dirs.push(PathBuf::from("/usr/local/share/grc"));
+    dirs.push(PathBuf::from("/usr/share/grc"));
+    dirs
 }
 
-pub fn find_config_file(matches: &clap::ArgMatches, args: &[String]) -> String {
-    let config_path: String = matches.get_one::<String>("config").expect("").to_string();
-    // matches.get_raw("config").unwrap().is_present();
-    let command_name = &args[0];
+fn search_path() -> Option<Vec<PathBuf>> {
+    env::var_os("RCZ_PATH").filter(|p| !p.is_empty()).map(|p| env::split_paths(&p).collect())
+}
 
-    let file = File::open(config_path).unwrap();
-    let reader = BufReader::new(file);
+// The main configuration files to consult, in order: `config` alone if one
+// was given (rcz's --config), otherwise $RCZ_CONFIG, rcz.conf in each of
+// config_dirs(), /etc/grc.conf unless $RCZ_PATH is set, and finally the
+// built-in configuration.
+pub fn main_config_files(config: Option<&Path>) -> Vec<PathBuf> {
+    if let Some(config_path) = config {
+        debug!("using the main config given with --config: {}", config_path.display());
+        return vec![config_path.to_path_buf()];
+    }
 
-    for line in reader.lines() {
-        let line = line.unwrap().trim().to_string();
-        if line.starts_with("#") || line.is_empty() {
-            continue;
+    let mut candidates = Vec::new();
+    if let Some(path) = env::var_os("RCZ_CONFIG").filter(|p| !p.is_empty()) {
+        candidates.push(PathBuf::from(path));
+    }
+    candidates.extend(config_dirs().into_iter().map(|dir| dir.join("rcz.conf")));
+    if search_path().is_none() {
+        candidates.push(PathBuf::from("/etc/grc.conf"));
+    }
+    candidates.retain(|path| {
+        let found = path.is_file();
+        if !found {
+            trace!("no main config at {}", path.display());
         }
+        found
+    });
+    candidates.push(PathBuf::from(builtin::BUILTIN_MAIN_CONFIG));
+    candidates
+}
+
+// Locates a profile named in a main configuration. Relative names are looked
+// up next to that configuration, then in config_dirs() and grc's directories,
+// and finally among the built-in profiles.
+pub fn resolve_profile(name: &str, main_config: &Path) -> Option<PathBuf> {
+    let path = Path::new(name);
+    if path.is_absolute() {
+        return path.is_file().then(|| path.to_path_buf());
+    }
 
-        let parts: Vec<&str> = line.split('=').collect();
-        if parts.len() == 2 {
-            let regex_pattern = parts[0].trim();
-            let config_file = parts[1].trim();
-            if regex::Regex::new(regex_pattern)
-                .unwrap()
-                .is_match(command_name)
-            {
-                return config_file.to_string();
+    let is_builtin = main_config.to_string_lossy().starts_with(builtin::BUILTIN_PREFIX);
+    main_config
+        .parent()
+        .filter(|_| !is_builtin)
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(config_dirs())
+        .chain(grc_dirs())
+        .map(|dir| dir.join(path))
+        .find(|path| {
+            let found = path.is_file();
+            if !found {
+                trace!("profile `{}` is not at {}", name, path.display());
             }
+            found
+        })
+        .or_else(|| builtin::builtin_profile(name).map(PathBuf::from))
+}
+
+// Returns the profile for the command, or an empty string when none of the
+// main configurations has a matching pattern.
+//
+// Like grc, patterns are matched against the whole command line, its words
+// joined by spaces, so that `^git log` and `^git diff` can pick different
+// profiles. When the command was given as a path, the line with the path
+// reduced to its basename is tried too, so `^ping` also matches
+// `/usr/bin/ping`. Configurations are consulted in order and, within each,
+// the first matching entry wins.
+pub fn find_config_file(config: Option<&Path>, args: &[String]) -> Result<String> {
+    let command_lines = command_lines(args);
+    debug!("looking for a profile for {:?}", command_lines);
+    for main_config in main_config_files(config) {
+        if let Some(profile) = find_in_main_config(&main_config, &command_lines)? {
+            return Ok(profile.to_string_lossy().into_owned());
         }
+        debug!("{}: no pattern matches", main_config.display());
     }
+    debug!("no main config has a pattern for the command, so its output is left as it is");
+    Ok(String::new())
+}
+
+// The command line as given, followed by the same line with the executable
+// reduced to its basename when that differs.
+pub fn command_lines(args: &[String]) -> Vec<String> {
+    let mut lines = vec![args.join(" ")];
+    if let Some((command, rest)) = args.split_first() {
+        let basename = Path::new(command).file_name().map(|name| name.to_string_lossy());
+        if let Some(basename) = basename.filter(|name| name != command) {
+            let mut words = vec![basename.into_owned()];
+            words.extend(rest.iter().cloned());
+            lines.push(words.join(" "));
+        }
+    }
+    lines
+}
 
-    String::new()
-}
\ No newline at end of file
+fn find_in_main_config(main_config: &Path, command_lines: &[String]) -> Result<Option<PathBuf>> {
+    let config_path = main_config.to_string_lossy();
+
+    for entry in read_main_config(&config_path)? {
+        let regex = regex::Regex::new(&entry.pattern).map_err(|e| RczError::regex(&config_path, entry.line, e))?;
+        if let Some(line) = command_lines.iter().find(|line| regex.is_match(line)) {
+            let profile = resolve_profile(&entry.profile, main_config).ok_or_else(|| {
+                RczError::config(&config_path, entry.line, format!("profile `{}` not found", entry.profile))
+            })?;
+            debug!(
+                "{}:{}: `{}` matches `{}`, using profile `{}` at {}",
+                config_path, entry.line, entry.pattern, line, entry.profile, profile.display()
+            );
+            return Ok(Some(profile));
+        }
+    }
+
+    Ok(None)
+}
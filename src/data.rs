@@ -1,8 +1,9 @@
+#![allow(clippy::enum_variant_names)]
+
 use std::collections::HashSet;
 use fancy_regex::Regex;
 
-
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum CgrcAttrib {
     CgrcNone = -1,
     CgrcReset = 0,
@@ -96,6 +97,166 @@ pub enum LcLogColor {
     LcForgColDefault = 30 + CgrcColor::LcDefault as isize,
 }
 
+// Colours beyond the 16 basic ones: an entry of the xterm 256 colour palette
+// or a 24-bit RGB value.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum LcExtColor {
+    LcExtIndexed(u8),
+    LcExtRgb(u8, u8, u8),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+pub enum CgrcColorDepth {
+    CgrcDepth16,
+    CgrcDepth256,
+    CgrcDepthTrue,
+}
+
+impl CgrcColorDepth {
+    // Guesses what the terminal supports from COLORTERM and TERM.
+    pub fn detect() -> CgrcColorDepth {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" || term.contains("truecolor") || term.contains("direct") {
+            CgrcColorDepth::CgrcDepthTrue
+        } else if term.contains("256color") {
+            CgrcColorDepth::CgrcDepth256
+        } else {
+            CgrcColorDepth::CgrcDepth16
+        }
+    }
+}
+
+impl LcExtColor {
+    pub fn to_rgb(self) -> (u8, u8, u8) {
+        match self {
+            LcExtColor::LcExtRgb(r, g, b) => (r, g, b),
+            LcExtColor::LcExtIndexed(n) => ansi256_to_rgb(n),
+        }
+    }
+
+    // Returns the closest colour the given depth can display; at 16 colours
+    // this is an index into FORG_ANSI16 / BACK_ANSI16 rather than a colour.
+    pub fn downsample(self, depth: CgrcColorDepth) -> Result<LcExtColor, usize> {
+        match (self, depth) {
+            (_, CgrcColorDepth::CgrcDepthTrue) => Ok(self),
+            (LcExtColor::LcExtIndexed(_), CgrcColorDepth::CgrcDepth256) => Ok(self),
+            (LcExtColor::LcExtRgb(r, g, b), CgrcColorDepth::CgrcDepth256) => Ok(LcExtColor::LcExtIndexed(rgb_to_ansi256(r, g, b))),
+            (LcExtColor::LcExtIndexed(n), CgrcColorDepth::CgrcDepth16) if n < 16 => Err(n as usize),
+            (_, CgrcColorDepth::CgrcDepth16) => {
+                let (r, g, b) = self.to_rgb();
+                Err(nearest_ansi16(r, g, b))
+            }
+        }
+    }
+
+    fn sgr(self, base: u8) -> String {
+        match self {
+            LcExtColor::LcExtIndexed(n) => format!("{};5;{}", base, n),
+            LcExtColor::LcExtRgb(r, g, b) => format!("{};2;{};{};{}", base, r, g, b),
+        }
+    }
+}
+
+pub const FORG_ANSI16: [LcLogColor; 16] = [
+    LcLogColor::LcForgColBlack,
+    LcLogColor::LcForgColRed,
+    LcLogColor::LcForgColGreen,
+    LcLogColor::LcForgColYellow,
+    LcLogColor::LcForgColBlue,
+    LcLogColor::LcForgColMagenta,
+    LcLogColor::LcForgColCyan,
+    LcLogColor::LcForgColWhite,
+    LcLogColor::LcForgBrightColBlack,
+    LcLogColor::LcForgBrightColRed,
+    LcLogColor::LcForgBrightColGreen,
+    LcLogColor::LcForgBrightColYellow,
+    LcLogColor::LcForgBrightColBlue,
+    LcLogColor::LcForgBrightColMagenta,
+    LcLogColor::LcForgBrightColCyan,
+    LcLogColor::LcForgBrightColWhite,
+];
+
+pub const BACK_ANSI16: [LcBackColor; 16] = [
+    LcBackColor::LcBackColBlack,
+    LcBackColor::LcBackColRed,
+    LcBackColor::LcBackColGreen,
+    LcBackColor::LcBackColYellow,
+    LcBackColor::LcBackColBlue,
+    LcBackColor::LcBackColMagenta,
+    LcBackColor::LcBackColCyan,
+    LcBackColor::LcBackColWhite,
+    LcBackColor::LcBackBrightColBlack,
+    LcBackColor::LcBackBrightColRed,
+    LcBackColor::LcBackBrightColGreen,
+    LcBackColor::LcBackBrightColYellow,
+    LcBackColor::LcBackBrightColBlue,
+    LcBackColor::LcBackBrightColMagenta,
+    LcBackColor::LcBackBrightColCyan,
+    LcBackColor::LcBackBrightColWhite,
+];
+
+// The xterm default palette for the 16 basic colours.
+const ANSI16_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+pub fn ansi256_to_rgb(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..=15 => ANSI16_RGB[n as usize],
+        16..=231 => {
+            let n = n - 16;
+            (CUBE_LEVELS[(n / 36) as usize], CUBE_LEVELS[(n / 6 % 6) as usize], CUBE_LEVELS[(n % 6) as usize])
+        }
+        _ => {
+            let level = 8 + (n - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+// Picks the closer of the nearest 6x6x6 cube entry and the nearest grey.
+pub fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    let cube_index = |v: u8| CUBE_LEVELS.iter().enumerate().min_by_key(|(_, l)| (**l as i32 - v as i32).abs()).unwrap().0 as u8;
+    let (ri, gi, bi) = (cube_index(r), cube_index(g), cube_index(b));
+    let cube = 16 + 36 * ri + 6 * gi + bi;
+
+    let avg = (r as u32 + g as u32 + b as u32) / 3;
+    let grey = if avg < 8 { 232 } else { (232 + ((avg - 8) / 10).min(23)) as u8 };
+
+    if color_distance((r, g, b), ansi256_to_rgb(grey)) < color_distance((r, g, b), ansi256_to_rgb(cube)) {
+        grey
+    } else {
+        cube
+    }
+}
+
+pub fn nearest_ansi16(r: u8, g: u8, b: u8) -> usize {
+    (0..16).min_by_key(|&i| color_distance((r, g, b), ANSI16_RGB[i])).unwrap()
+}
+
+fn color_distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
 pub static COLORS_ATTRS: phf::Map<&'static str, CgrcAttrib> = phf::phf_map! {
     "none" => CgrcAttrib::CgrcNone,
     "unchanged" => CgrcAttrib::CgrcNone,
@@ -154,27 +315,60 @@ pub struct CgrcColorItem {
     pub attrs: HashSet<CgrcAttrib>,
     pub forg: LcLogColor,
     pub back: LcBackColor,
+    // When set, these take the place of forg and back in the escape sequence.
+    pub forg_ext: Option<LcExtColor>,
+    pub back_ext: Option<LcExtColor>,
     pub escape_seq: String,
     pub clear_seq: String,
 }
 
 impl CgrcColorItem {
     pub fn new(attrs: HashSet<CgrcAttrib>, forg: LcLogColor, back: LcBackColor) -> CgrcColorItem {
-        let escape_seq = CgrcColorItem::build_escape_seq(&attrs, &forg, &back);
+        CgrcColorItem::new_ext(attrs, forg, back, None, None)
+    }
+
+    pub fn new_ext(
+        attrs: HashSet<CgrcAttrib>,
+        forg: LcLogColor,
+        back: LcBackColor,
+        forg_ext: Option<LcExtColor>,
+        back_ext: Option<LcExtColor>,
+    ) -> CgrcColorItem {
+        let escape_seq = CgrcColorItem::build_escape_seq(&attrs, &forg, &back, forg_ext, back_ext);
         let clear_seq = CgrcColorItem::build_clear_seq(&attrs);
         CgrcColorItem {
             attrs,
             forg,
             back,
+            forg_ext,
+            back_ext,
             escape_seq,
             clear_seq,
         }
     }
 
-    fn build_escape_seq(attrs: &HashSet<CgrcAttrib>, forg: &LcLogColor, back: &LcBackColor) -> String {
-        let mut seq = format!("{}[{};{}", 0x1b as char, *forg as u8, *back as u8);
-        for attr in attrs {
-            seq += &format!(";{}", *attr as u8);
+    // True when the item only selects the terminal's default colours, which
+    // needs no escape sequence at all.
+    pub fn is_plain(&self) -> bool {
+        self.attrs.is_empty()
+            && self.forg == LcLogColor::LcForgColDefault
+            && self.back == LcBackColor::LcBackColDefault
+            && self.forg_ext.is_none()
+            && self.back_ext.is_none()
+    }
+
+    fn build_escape_seq(
+        attrs: &HashSet<CgrcAttrib>,
+        forg: &LcLogColor,
+        back: &LcBackColor,
+        forg_ext: Option<LcExtColor>,
+        back_ext: Option<LcExtColor>,
+    ) -> String {
+        let forg = forg_ext.map_or((*forg as u8).to_string(), |c| c.sgr(38));
+        let back = back_ext.map_or((*back as u8).to_string(), |c| c.sgr(48));
+        let mut seq = format!("{}[{};{}", 0x1b as char, forg, back);
+        for attr in sorted_attrs(attrs) {
+            seq += &format!(";{}", attr as u8);
         }
         seq += "m";
         seq
@@ -187,7 +381,7 @@ impl CgrcColorItem {
             LcLogColor::LcForgColDefault as u8,
             LcBackColor::LcBackColDefault as u8
         );
-        for attr in attrs {
+        for attr in sorted_attrs(attrs) {
             seq += &format!(";{}", colors_attr_clear(&attr) as u8);
         }
         seq += "m";
@@ -195,12 +389,125 @@ impl CgrcColorItem {
     }
 }
 
+// The test of one `values.GROUP=` entry against the text a group captured.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CgrcValueTest {
+    // Thresholds compare the number the text starts with.
+    Less(f64),
+    LessEq(f64),
+    Greater(f64),
+    GreaterEq(f64),
+    // The whole text, with each `x` standing for any digit, as in `2xx`.
+    Pattern(String),
+    Else,
+}
+
+impl CgrcValueTest {
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.trim();
+        match self {
+            CgrcValueTest::Pattern(pattern) => {
+                pattern.chars().count() == text.chars().count()
+                    && pattern.chars().zip(text.chars()).all(|(p, c)| p == c || (p == 'x' && c.is_ascii_digit()))
+            }
+            CgrcValueTest::Else => true,
+            _ => {
+                let Some(value) = leading_number(text) else { return false };
+                match *self {
+                    CgrcValueTest::Less(limit) => value < limit,
+                    CgrcValueTest::LessEq(limit) => value <= limit,
+                    CgrcValueTest::Greater(limit) => value > limit,
+                    CgrcValueTest::GreaterEq(limit) => value >= limit,
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+// The entries of one `values.GROUP=` line, tried in order.
+pub type CgrcValueColors = Vec<(CgrcValueTest, Option<CgrcColorItem>)>;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CgrcScale {
+    CgrcScaleLinear,
+    CgrcScaleLog,
+}
+
+// A `gradient.GROUP=` line: the number a group captured is placed between
+// the stops, and the colour between theirs is what it gets.
 #[derive(Clone, Debug)]
+pub struct CgrcGradient {
+    pub scale: CgrcScale,
+    // Values in increasing order, each with its colour.
+    pub stops: Vec<(f64, (u8, u8, u8))>,
+    // Whether the gradient colours the background rather than the text.
+    pub back: bool,
+    pub depth: CgrcColorDepth,
+}
+
+impl CgrcGradient {
+    // The colour for a captured text, or None when it does not start with a
+    // number. Values beyond the first or last stop take that stop's colour.
+    pub fn color_for(&self, text: &str) -> Option<CgrcColorItem> {
+        let position = |value: f64| match self.scale {
+            CgrcScale::CgrcScaleLinear => value,
+            CgrcScale::CgrcScaleLog => value.max(f64::MIN_POSITIVE).ln(),
+        };
+        let x = position(leading_number(text.trim())?);
+        let (first, last) = (self.stops.first()?, self.stops.last()?);
+        let (r, g, b) = match self.stops.iter().position(|&(at, _)| x <= position(at)) {
+            Some(0) => first.1,
+            None => last.1,
+            Some(i) => {
+                let ((from, low), (to, high)) = (self.stops[i - 1], self.stops[i]);
+                let t = (x - position(from)) / (position(to) - position(from));
+                let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+                (mix(low.0, high.0), mix(low.1, high.1), mix(low.2, high.2))
+            }
+        };
+
+        let (mut forg, mut back) = (LcLogColor::LcForgColDefault, LcBackColor::LcBackColDefault);
+        let (mut forg_ext, mut back_ext) = (None, None);
+        match (self.back, LcExtColor::LcExtRgb(r, g, b).downsample(self.depth)) {
+            (false, Ok(ext)) => forg_ext = Some(ext),
+            (false, Err(index)) => forg = FORG_ANSI16[index],
+            (true, Ok(ext)) => back_ext = Some(ext),
+            (true, Err(index)) => back = BACK_ANSI16[index],
+        }
+        Some(CgrcColorItem::new_ext(HashSet::new(), forg, back, forg_ext, back_ext))
+    }
+}
+
+// The number at the start of a text such as `12.5 ms` or `45%`.
+fn leading_number(text: &str) -> Option<f64> {
+    let end = text
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || (i == 0 && (c == '-' || c == '+'))))
+        .map_or(text.len(), |(i, _)| i);
+    text[..end].parse().ok()
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct CgrcConfItem {
     pub regex: Option<Regex>,
-    pub colors: Vec<CgrcColorItem>,
+    // One entry per group, group 0 being the whole match. `None` is an
+    // `unchanged` entry, which leaves its group as it is.
+    pub colors: Vec<Option<CgrcColorItem>>,
+    // `colours.NAME=` entries by the index of the group they name. They are
+    // moved into `colors` when the rule is finished, taking the place of any
+    // positional entry for the same group.
+    pub named_colors: Vec<(usize, Option<CgrcColorItem>)>,
+    // `values.GROUP=` entries by group index: the colour of the first test
+    // the captured text passes replaces the group's colour.
+    pub value_colors: Vec<(usize, CgrcValueColors)>,
+    // `gradient.GROUP=` entries by group index, for groups no values= test
+    // gave a colour.
+    pub gradients: Vec<(usize, CgrcGradient)>,
     pub skip: Option<bool>,
     pub count_mode: Option<CgrcCountMode>,
+    // Most lines a match may span in multi-line mode (`lines=`).
+    pub lines: Option<usize>,
 }
 
 impl CgrcConfItem {
@@ -208,8 +515,12 @@ impl CgrcConfItem {
         CgrcConfItem {
             regex: None,
             colors: vec![],
+            named_colors: vec![],
+            value_colors: vec![],
+            gradients: vec![],
             skip: None,
             count_mode: None,
+            lines: None,
         }
     }
 }
@@ -217,10 +528,7 @@ impl CgrcConfItem {
 impl PartialEq for CgrcConfItem {
     fn eq(&self, other: &Self) -> bool {
         match &self.regex {
-            None => match other.regex {
-                None => true,
-                Some(_) => false,
-            },
+            None => other.regex.is_none(),
             Some(r1) => match &other.regex {
                 None => false,
                 Some(r2) => r1.as_str() == r2.as_str(),
@@ -229,6 +537,7 @@ impl PartialEq for CgrcConfItem {
     }
 }
 
+#[derive(Default)]
 pub struct CgrcConf {
     pub items: Vec<CgrcConfItem>,
     pub description: Option<String>,
@@ -243,14 +552,23 @@ impl CgrcConf {
     }
 }
 
+// HashSet iteration order is unspecified, so attributes are emitted in a fixed
+// order to keep escape sequences stable.
+fn sorted_attrs(attrs: &HashSet<CgrcAttrib>) -> Vec<CgrcAttrib> {
+    let mut sorted: Vec<CgrcAttrib> = attrs.iter().copied().collect();
+    sorted.sort();
+    sorted
+}
+
 fn colors_attr_clear(attr: &CgrcAttrib) -> CgrcResetAttrib {
     match attr {
-        CgrcAttrib::CgrcBright => CgrcResetAttrib::CgrcResetBright,
+        // SGR 21 is double underline on most terminals; 22 resets both bold and dim.
+        CgrcAttrib::CgrcBright => CgrcResetAttrib::CgrcResetDim,
         CgrcAttrib::CgrcDim => CgrcResetAttrib::CgrcResetDim,
         CgrcAttrib::CgrcItalic => CgrcResetAttrib::CgrcResetItalic,
         CgrcAttrib::CgrcUnderline => CgrcResetAttrib::CgrcResetUnderline,
         CgrcAttrib::CgrcBlink => CgrcResetAttrib::CgrcResetBlink,
-        CgrcAttrib::CgrcRapidBlink => CgrcResetAttrib::CgrcResetRapidBlink,
+        CgrcAttrib::CgrcRapidBlink => CgrcResetAttrib::CgrcResetBlink,
         CgrcAttrib::CgrcReverse => CgrcResetAttrib::CgrcResetReverse,
         CgrcAttrib::CgrcHidden => CgrcResetAttrib::CgrcResetHidden,
         CgrcAttrib::CgrcStrikethrough => CgrcResetAttrib::CgrcResetStrikethrough,
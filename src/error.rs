@@ -0,0 +1,51 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum RczError {
+    // An I/O failure, with the file or operation it happened on.
+    Io { context: String, source: io::Error },
+    Regex { file: String, line: usize, source: Box<dyn Error + Send + Sync> },
+    Config { file: String, line: usize, message: String },
+    Spawn { command: String, source: io::Error },
+    MissingConfig,
+}
+
+pub type Result<T> = std::result::Result<T, RczError>;
+
+impl RczError {
+    pub fn io(context: impl Into<String>, source: io::Error) -> RczError {
+        RczError::Io { context: context.into(), source }
+    }
+
+    pub fn config(file: &str, line: usize, message: impl Into<String>) -> RczError {
+        RczError::Config { file: file.to_string(), line, message: message.into() }
+    }
+
+    pub fn regex(file: &str, line: usize, source: impl Error + Send + Sync + 'static) -> RczError {
+        RczError::Regex { file: file.to_string(), line, source: Box::new(source) }
+    }
+}
+
+impl fmt::Display for RczError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RczError::Io { context, source } => write!(f, "{}: {}", context, source),
+            RczError::Regex { file, line, source } => write!(f, "{}:{}: invalid regex: {}", file, line, source),
+            RczError::Config { file, line, message } => write!(f, "{}:{}: {}", file, line, message),
+            RczError::Spawn { command, source } => write!(f, "failed to start {}: {}", command, source),
+            RczError::MissingConfig => write!(f, "no configuration file specified"),
+        }
+    }
+}
+
+impl Error for RczError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RczError::Io { source, .. } | RczError::Spawn { source, .. } => Some(source),
+            RczError::Regex { source, .. } => Some(source.as_ref()),
+            RczError::Config { .. } | RczError::MissingConfig => None,
+        }
+    }
+}
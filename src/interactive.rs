@@ -0,0 +1,338 @@
+use fancy_regex::Regex;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+use rustcolorizer::error::{RczError, Result};
+use rustcolorizer::rczat::Colorizer;
+
+// Most sample lines shown by a preview.
+const PREVIEW_LINES: usize = 20;
+
+const HELP: &str = "\
+Commands:
+  r REGEX        set the regexp of the rule being written and preview its matches
+  c COLOURS      set its colours, for example `bold green, yellow`
+  o KEY=VALUE    set another option such as count=stop or skip=yes (KEY= removes it)
+  a [N]          add the rule, at position N or at the end
+  e N            take rule N out of the list to change it
+  d N            delete rule N
+  m N M          move rule N to position M
+  l              list the rules
+  p              preview the sample with all the rules
+  s CMD|FILE     take the sample from a command's output or a file
+  w [FILE]       save the rules as a profile
+  q              quit
+";
+
+// A rule as it is written to the profile.
+#[derive(Clone, Default)]
+struct Rule {
+    regexp: String,
+    colours: String,
+    // Other `key=value` lines, such as `count=stop`.
+    options: Vec<String>,
+}
+
+impl Rule {
+    fn to_conf(&self) -> String {
+        let mut conf = format!("regexp={}\n", self.regexp);
+        if !self.colours.is_empty() {
+            conf.push_str(&format!("colours={}\n", self.colours));
+        }
+        for option in &self.options {
+            conf.push_str(option);
+            conf.push('\n');
+        }
+        conf.push_str("-\n");
+        conf
+    }
+
+    fn set_option(&mut self, option: &str) {
+        let key = option.split('=').next().unwrap_or_default();
+        self.options.retain(|o| o.split('=').next() != Some(key));
+        if !option.ends_with('=') {
+            self.options.push(option.to_string());
+        }
+    }
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "regexp={}  colours={}", self.regexp, self.colours)?;
+        for option in &self.options {
+            write!(f, "  {}", option)?;
+        }
+        Ok(())
+    }
+}
+
+struct Session {
+    sample: Vec<String>,
+    rules: Vec<Rule>,
+    draft: Rule,
+    path: Option<String>,
+    colour: bool,
+    saved: bool,
+}
+
+// Interactive profile authoring: rules are tried out against sample output
+// and then saved in the profile format. With --config, that profile is loaded
+// first and is where the rules are saved by default.
+pub fn run_interactive_mode(path: Option<String>, colour: bool, args: &[String]) -> Result<()> {
+    let rules = match &path {
+        Some(path) if Path::new(path).exists() => load_rules(path)?,
+        _ => Vec::new(),
+    };
+    let mut session = Session {
+        sample: Vec::new(),
+        rules,
+        draft: Rule::default(),
+        path,
+        colour,
+        saved: true,
+    };
+
+    let mut input = io::stdin().lock().lines();
+    if !args.is_empty() {
+        session.sample = read_sample(args)?;
+    } else {
+        prompt("Sample command or file: ");
+        if let Some(line) = input.next() {
+            let line = line.map_err(|e| RczError::io("stdin", e))?;
+            session.sample = sample_from_line(line.trim())?;
+        }
+    }
+    println!("{} sample lines. Type h for help.", session.sample.len());
+
+    loop {
+        prompt("rcz> ");
+        let Some(line) = input.next() else { break };
+        let line = line.map_err(|e| RczError::io("stdin", e))?;
+        let (command, rest) = line.trim_start().split_once(' ').unwrap_or((line.trim(), ""));
+        let result = match command {
+            "" => Ok(()),
+            "q" => break,
+            "h" | "?" => {
+                print!("{}", HELP);
+                Ok(())
+            }
+            _ => session.run(command, rest),
+        };
+        if let Err(e) = result {
+            println!("error: {}", e);
+        }
+    }
+
+    if !session.saved {
+        println!("Rules were not saved.");
+    }
+    Ok(())
+}
+
+impl Session {
+    // Runs one command, returning the message to show if it fails.
+    fn run(&mut self, command: &str, rest: &str) -> std::result::Result<(), String> {
+        match command {
+            "r" => {
+                Regex::new(rest).map_err(|e| format!("invalid regex: {}", e))?;
+                self.draft.regexp = rest.to_string();
+                self.preview_draft()
+            }
+            "c" => {
+                self.draft.colours = rest.trim().to_string();
+                self.preview_draft()
+            }
+            "o" => {
+                if !rest.contains('=') {
+                    return Err("expected KEY=VALUE".to_string());
+                }
+                self.draft.set_option(rest.trim());
+                self.preview_draft()
+            }
+            "a" => {
+                if self.draft.regexp.is_empty() {
+                    return Err("the rule has no regexp yet".to_string());
+                }
+                let at = match rest.trim() {
+                    "" => self.rules.len(),
+                    n => self.position(n, self.rules.len() + 1)?,
+                };
+                self.rules.insert(at, std::mem::take(&mut self.draft));
+                self.saved = false;
+                self.list();
+                Ok(())
+            }
+            "e" => {
+                let n = self.position(rest, self.rules.len())?;
+                self.draft = self.rules.remove(n);
+                self.saved = false;
+                println!("editing: {}", self.draft);
+                Ok(())
+            }
+            "d" => {
+                let n = self.position(rest, self.rules.len())?;
+                self.rules.remove(n);
+                self.saved = false;
+                self.list();
+                Ok(())
+            }
+            "m" => {
+                let (from, to) = rest.trim().split_once(' ').ok_or_else(|| "expected N M".to_string())?;
+                let from = self.position(from, self.rules.len())?;
+                let to = self.position(to, self.rules.len())?;
+                let rule = self.rules.remove(from);
+                self.rules.insert(to, rule);
+                self.saved = false;
+                self.list();
+                Ok(())
+            }
+            "l" => {
+                self.list();
+                Ok(())
+            }
+            "p" => {
+                let conf = self.rules.iter().map(Rule::to_conf).collect::<String>();
+                let mut colorizer = Colorizer::from_str(&conf).map_err(|e| e.to_string())?;
+                for line in self.sample.iter().take(PREVIEW_LINES) {
+                    println!("{}", self.paint(&mut colorizer, line));
+                }
+                if self.sample.len() > PREVIEW_LINES {
+                    println!("... {} more lines", self.sample.len() - PREVIEW_LINES);
+                }
+                Ok(())
+            }
+            "s" => {
+                self.sample = sample_from_line(rest.trim()).map_err(|e| e.to_string())?;
+                println!("{} sample lines.", self.sample.len());
+                Ok(())
+            }
+            "w" => {
+                let path = match rest.trim() {
+                    "" => self.path.clone().ok_or_else(|| "no file given".to_string())?,
+                    path => path.to_string(),
+                };
+                let conf = self.rules.iter().map(Rule::to_conf).collect::<String>();
+                fs::write(&path, conf).map_err(|e| format!("{}: {}", path, e))?;
+                println!("Saved {} rules to {}", self.rules.len(), path);
+                self.path = Some(path);
+                self.saved = true;
+                Ok(())
+            }
+            _ => Err(format!("unknown command `{}`, type h for help", command)),
+        }
+    }
+
+    // Shows the sample lines the rule being written matches, coloured by it
+    // alone.
+    fn preview_draft(&self) -> std::result::Result<(), String> {
+        if self.draft.regexp.is_empty() {
+            return Ok(());
+        }
+        let regex = Regex::new(&self.draft.regexp).map_err(|e| format!("invalid regex: {}", e))?;
+        let mut colorizer = Colorizer::from_str(&self.draft.to_conf()).map_err(|e| e.to_string())?;
+        let matching: Vec<&String> = self.sample.iter().filter(|line| regex.is_match(line).unwrap_or(false)).collect();
+        for line in matching.iter().take(PREVIEW_LINES) {
+            println!("{}", self.paint(&mut colorizer, line));
+        }
+        println!("{} of {} sample lines match", matching.len(), self.sample.len());
+        Ok(())
+    }
+
+    fn paint(&self, colorizer: &mut Colorizer, line: &str) -> String {
+        if self.colour {
+            colorizer.colorize(line)
+        } else {
+            line.to_string()
+        }
+    }
+
+    fn list(&self) {
+        if self.rules.is_empty() {
+            println!("No rules yet.");
+        }
+        for (i, rule) in self.rules.iter().enumerate() {
+            println!("{:>3}  {}", i + 1, rule);
+        }
+    }
+
+    // Parses a 1-based rule number, up to `max`, into an index.
+    fn position(&self, n: &str, max: usize) -> std::result::Result<usize, String> {
+        match n.trim().parse::<usize>() {
+            Ok(n) if n >= 1 && n <= max => Ok(n - 1),
+            _ => Err(format!("expected a rule number from 1 to {}", max)),
+        }
+    }
+}
+
+fn prompt(text: &str) {
+    print!("{}", text);
+    let _ = io::stdout().flush();
+}
+
+// Reads the sample from a file if that is all that was given, and otherwise
+// from the output of the command.
+fn read_sample(args: &[String]) -> Result<Vec<String>> {
+    if let [path] = args {
+        if Path::new(path).is_file() {
+            let contents = fs::read(path).map_err(|e| RczError::io(path, e))?;
+            return Ok(sample_lines(&contents));
+        }
+    }
+    let output = Command::new(&args[0])
+        .args(&args[1..])
+        .output()
+        .map_err(|source| RczError::Spawn { command: args[0].clone(), source })?;
+    let mut contents = output.stdout;
+    contents.extend(output.stderr);
+    Ok(sample_lines(&contents))
+}
+
+// A typed sample is a file name or a shell command line.
+fn sample_from_line(line: &str) -> Result<Vec<String>> {
+    if line.is_empty() {
+        return Ok(Vec::new());
+    }
+    if Path::new(line).is_file() {
+        return read_sample(&[line.to_string()]);
+    }
+    read_sample(&["sh".to_string(), "-c".to_string(), line.to_string()])
+}
+
+fn sample_lines(contents: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(contents).lines().map(str::to_string).collect()
+}
+
+// Reads a profile back into rules. Comments are not kept.
+fn load_rules(path: &str) -> Result<Vec<Rule>> {
+    // Reject profiles rcz itself would not load.
+    Colorizer::from_file(path)?;
+    let contents = fs::read_to_string(path).map_err(|e| RczError::io(path, e))?;
+
+    let mut rules = Vec::new();
+    let mut rule = Rule::default();
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(regexp) = line.strip_prefix("regexp=") {
+            if !rule.regexp.is_empty() {
+                rules.push(std::mem::take(&mut rule));
+            }
+            rule.regexp = regexp.to_string();
+        } else if let Some(colours) = line.strip_prefix("colours=") {
+            rule.colours = colours.to_string();
+        } else if line == "-" {
+            if !rule.regexp.is_empty() {
+                rules.push(std::mem::take(&mut rule));
+            }
+        } else {
+            rule.options.push(line.to_string());
+        }
+    }
+    if !rule.regexp.is_empty() {
+        rules.push(rule);
+    }
+    Ok(rules)
+}
@@ -0,0 +1,22 @@
+//! Colorizes text line by line using grc style configuration files.
+//!
+//! ```
+//! use rustcolorizer::Colorizer;
+//!
+//! let mut colorizer: Colorizer = "regexp=\\d+ ms\ncolours=green\n".parse().unwrap();
+//! assert_eq!(colorizer.colorize("time 12 ms"), "time \x1b[32;49m12 ms\x1b[39;49m");
+//! ```
+
+pub mod builtin;
+pub mod check;
+pub mod config;
+pub mod data;
+pub mod error;
+pub mod rczat;
+
+pub use data::{
+    CgrcAttrib, CgrcColorItem, CgrcConf, CgrcConfItem, CgrcCountMode, CgrcGradient, CgrcScale, CgrcValueTest,
+    LcBackColor, LcExtColor, LcLogColor,
+};
+pub use error::{RczError, Result};
+pub use rczat::{load_main_config, Colorizer, MultilineColorizer};
@@ -1,26 +1,45 @@
-use clap::{Arg, ArgAction, Command};
-// use clap_complete::{generate, Shell};
-use std::env;
-use std::io::{self};
-use std::sync::{Arc, atomic::{AtomicBool}};
+use clap::{builder::PossibleValuesParser, value_parser, Arg, ArgAction, ArgMatches, Command};
+use clap_complete::{generate, Shell};
+use log::LevelFilter;
+use std::fs::File;
+use std::io::{self, BufReader, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
-mod config;
+mod aliases;
+mod interactive;
+mod pty;
 mod signal_handler;
 mod runner;
-mod rczat;
-mod data;
+
+use rustcolorizer::{check, config, rczat};
+use rustcolorizer::config::ColourMode;
+use rustcolorizer::data::CgrcConf;
+use rustcolorizer::error::{RczError, Result};
 
 fn main() {
-    // Initialize the command-line argument parser
-    let matches = Command::new("RustColorizer")
+    let matches = cli().get_matches();
+
+    match run(&matches) {
+        Ok(status) => exit(status),
+        Err(e) => {
+            eprintln!("rcz: {}", e);
+            exit(1);
+        }
+    }
+}
+
+// The command-line argument parser
+fn cli() -> Command {
+    Command::new("RustColorizer")
+        .bin_name("rcz")
         .version("1.0")
         .about("A tool to colorize command outputs")
         .arg(Arg::new("stderr")
             .short('e')
             .long("stderr")
             .action(ArgAction::SetTrue)
-            .help("Redirect stderr"))
+            .help("Redirect stderr. If this option is selected, do not automatically redirect stdout"))
         .arg(Arg::new("stdout")
             .short('s')
             .long("stdout")
@@ -30,10 +49,17 @@ fn main() {
             .short('c')
             .long("config")
             // .takes_value(true)
-            .help("Use this configuration file for rczat"))
+            .help("Use this configuration file for rczat (the profile itself with --filter and --interactive)"))
+        .arg(Arg::new("filter")
+            .long("filter")
+            .action(ArgAction::SetTrue)
+            .requires("config")
+            .conflicts_with_all(["pty", "stderr", "stdout"])
+            .help("Colorize standard input, or the given files, with the --config profile instead of running a command"))
         .arg(Arg::new("colour")
             .long("colour")
-            // .takes_value(true)
+            .value_parser(["on", "off", "auto"])
+            .default_value("auto")
             .help("Set colour mode (on, off, auto)"))
         .arg(Arg::new("pty")
             .long("pty")
@@ -42,52 +68,61 @@ fn main() {
         .arg(Arg::new("multiline")
             .long("multiline")
             .action(ArgAction::SetTrue)
-            .help("Enable multi-line mode for command execution"))
+            .help("Let rules with lines=N match across up to N lines, holding output back briefly"))
         .arg(Arg::new("interactive")
             .long("interactive")
             .action(ArgAction::SetTrue)
-            .help("Enter interactive mode to configure new commands"))
+            .help("Write a profile interactively, trying rules out on the output of COMMAND or a file"))
         .arg(Arg::new("debug")
             .long("debug")
-            .action(ArgAction::SetTrue)
-            .help("Enable debugging output"))
+            .value_name("FILE")
+            .num_args(0..=1)
+            .require_equals(true)
+            .help("Explain which config is used and which rules match each line, on stderr or in FILE"))
         .arg(Arg::new("aliases")
             .long("aliases")
-            .action(ArgAction::SetTrue)
-            .help("Generate shell aliases for configured commands"))
+            .value_name("SHELL")
+            .num_args(0..=1)
+            .value_parser(["bash", "zsh", "fish", "nushell"])
+            .help("Generate shell aliases for configured commands that are installed, for the given shell or $SHELL"))
         .arg(Arg::new("completion")
             .long("completion")
-            // .takes_value(true)
-            // .possible_values(Shell::possible_values())
+            .value_name("SHELL")
+            .value_parser(value_parser!(Shell))
             .help("Generate shell completions for the specified shell"))
         .arg(Arg::new("COMMAND")
             .action(ArgAction::Append)
-            .help("Command and arguments to execute")
-            .required_unless_present_any(&["interactive", "debug", "aliases", "completion"]))
-        .get_matches();
+            .trailing_var_arg(true)
+            .allow_hyphen_values(true)
+            .help("Command and arguments to execute, or files to read with --filter")
+            .required_unless_present_any(["interactive", "aliases", "completion", "filter"]))
+        .subcommand_negates_reqs(true)
+        .subcommand(Command::new("check")
+            .about("Check configuration files for mistakes, exiting with status 1 if there are any")
+            .arg(Arg::new("FILE")
+                .action(ArgAction::Append)
+                .help("Main configurations or profiles to check [default: the main configurations in use]")))
+}
 
-    // Handle shell completion generation
-    // if let Some(shell) = matches.value_of("completion") {
-    //     generate_completions(shell);
-    //     return;
-    // }
+fn run(matches: &ArgMatches) -> Result<i32> {
+    init_logging(matches)?;
 
-    // Handle interactive mode
-    if matches.get_flag("interactive") {
-        println!("Entering interactive mode...");
-        run_interactive_mode();
-        return;
+    if let Some(check_matches) = matches.subcommand_matches("check") {
+        return run_check(matches, check_matches);
     }
 
-    // Handle alias generation
-    if matches.get_flag("aliases") {
-        generate_aliases();
-        return;
+    // Handle shell completion generation
+    if let Some(shell) = matches.get_one::<Shell>("completion") {
+        generate_completions(*shell, matches)?;
+        return Ok(0);
     }
 
-    // Setup signal handling for graceful termination
-    let term_now = Arc::new(AtomicBool::new(false));
-    signal_handler::setup_signal_handler(Arc::clone(&term_now));
+    // Handle alias generation
+    if matches.contains_id("aliases") {
+        let commands = config::configured_commands(config_path(matches))?;
+        aliases::generate_aliases(matches.get_one::<String>("aliases").map(String::as_str), &commands)?;
+        return Ok(0);
+    }
 
     // Get the command and its arguments
     let args: Vec<String> = matches
@@ -96,81 +131,155 @@ fn main() {
         .map(|s| s.to_string())
         .collect();
 
-    // Process the command with colorization or multi-line mode
-    if matches.get_flag("multiline") {
-        println!("Entering multi-line mode...");
-        run_multiline_mode(&args, term_now);
-    } else {
-        let config_file = config::find_config_file(&matches, &args);
-        println!("Executing command with config file: {}", config_file);
-        let status = runner::execute_command(&args, &config_file, &matches, term_now);
-        exit(status);
+    // Handle interactive mode, authoring a profile against sample output
+    if matches.get_flag("interactive") {
+        let colour = config::use_colour(colour_mode(matches), io::stdout().is_terminal());
+        interactive::run_interactive_mode(matches.get_one::<String>("config").cloned(), colour, &args)?;
+        return Ok(0);
+    }
+
+    // Handle filter mode, colorizing input that is already being produced
+    if matches.get_flag("filter") {
+        run_filter(matches, &args)?;
+        return Ok(0);
+    }
+
+    // Process the command with colorization
+    let config_file = config::find_config_file(config_path(matches), &args)?;
+    runner::execute_command(&args, &config_file, colour_mode(matches), matches)
+}
+
+fn colour_mode(matches: &ArgMatches) -> ColourMode {
+    match matches.get_one::<String>("colour").map(String::as_str) {
+        Some("on") => ColourMode::On,
+        Some("off") => ColourMode::Off,
+        _ => ColourMode::Auto,
     }
 }
 
-// Function to generate shell completions
-fn generate_completions(shell: &str) {
-    let mut app = Command::new("RustColorizer");
-    // let shell: Shell = shell.parse().unwrap();
-    // generate(shell, &mut app, "rcz", &mut io::stdout());
+fn config_path(matches: &ArgMatches) -> Option<&Path> {
+    matches.get_one::<String>("config").map(Path::new)
 }
 
-// Function to generate shell aliases
-fn generate_aliases() {
-    let config_path = "rcz.conf"; // Adjust this path as necessary
-    let commands = config::extract_commands_from_config(config_path);
-
-    let shell = match env::var("SHELL") {
-        Ok(val) => {
-            if val.contains("zsh") {
-                "zsh"
-            } else if val.contains("fish") {
-                "fish"
-            } else {
-                "bash"
-            }
+// Filter mode: colorizes stdin, or each of the given files in turn, with the
+// profile named by --config.
+fn run_filter(matches: &ArgMatches, files: &[String]) -> Result<()> {
+    let profile = matches.get_one::<String>("config").ok_or(RczError::MissingConfig)?;
+    // A bare profile name such as `conf.ping` is also looked for in the config directories.
+    let profile_path = config::resolve_profile(profile, Path::new(profile)).unwrap_or_else(|| profile.into());
+    let conf = if config::use_colour(colour_mode(matches), io::stdout().is_terminal()) {
+        rczat::load_main_config(&profile_path.to_string_lossy())?
+    } else {
+        CgrcConf::new()
+    };
+
+    let multiline = matches.get_flag("multiline");
+    let stdout = io::stdout();
+    let mut writer = stdout.lock();
+    let mut colorize = |reader: Box<dyn Read + Send>| {
+        if multiline {
+            rczat::colorize_stream_multiline(reader, &mut writer, &conf, rczat::FLUSH_TIMEOUT)
+        } else {
+            rczat::colorize_stream(BufReader::new(reader), &mut writer, &conf)
         }
-        Err(_) => "bash",
     };
+    let result = if files.is_empty() {
+        colorize(Box::new(io::stdin())).map_err(|e| RczError::io("stdin", e))
+    } else {
+        files.iter().try_for_each(|path| {
+            let file = File::open(path).map_err(|e| RczError::io(path, e))?;
+            colorize(Box::new(file)).map_err(|e| RczError::io(path, e))
+        })
+    };
+
+    // Stop quietly when the reader on the other end of a pipe goes away.
+    match result {
+        Err(RczError::Io { source, .. }) if source.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
 
-    let aliases = generate_aliases_for_shell(&commands, &shell);
+// Lints the given files, or else the main configurations in use, along with
+// the profiles they name. Each problem is printed as file:line: message.
+fn run_check(matches: &ArgMatches, check_matches: &ArgMatches) -> Result<i32> {
+    let files: Vec<PathBuf> = match check_matches.get_many::<String>("FILE") {
+        Some(files) => files.map(PathBuf::from).collect(),
+        None => config::main_config_files(config_path(matches)),
+    };
+    let (main_configs, profiles): (Vec<PathBuf>, Vec<PathBuf>) =
+        files.into_iter().partition(|file| check::is_main_config(file));
 
-    for alias in aliases {
-        println!("{}", alias);
+    let mut problems = check::check_main_configs(&main_configs);
+    for profile in profiles {
+        problems.extend(check::check_profile(&profile.to_string_lossy()));
+    }
+    for problem in &problems {
+        println!("{}", problem);
     }
+    Ok(if problems.is_empty() { 0 } else { 1 })
 }
 
-// Function to create aliases based on shell type
-fn generate_aliases_for_shell(commands: &[String], shell: &str) -> Vec<String> {
-    commands.iter().map(|cmd| {
-        match shell {
-            "bash" | "zsh" => format!("alias {}='rcz {}'", cmd, cmd),
-            "fish" => format!("alias {} 'rcz {}'", cmd, cmd),
-            _ => format!("alias {}='rcz {}'", cmd, cmd), // Default to bash-style
+// Logging follows RUST_LOG. --debug turns on rcz's own trace output as well,
+// on stderr or in the file given, so that it can be kept apart from the
+// colorized output.
+fn init_logging(matches: &ArgMatches) -> Result<()> {
+    let mut builder = env_logger::Builder::from_default_env();
+    if matches.contains_id("debug") {
+        builder.filter_module("rustcolorizer", LevelFilter::Trace).filter_module("rcz", LevelFilter::Trace);
+        if let Some(path) = matches.get_one::<String>("debug") {
+            let file = File::create(path).map_err(|e| RczError::io(path, e))?;
+            builder.target(env_logger::Target::Pipe(Box::new(file)));
         }
-    }).collect()
+    }
+    builder.init();
+    Ok(())
 }
 
-// Function to handle multi-line mode
-fn run_multiline_mode(args: &[String], term_now: Arc<AtomicBool>) {
-    println!("Multi-line mode is currently a basic placeholder implementation.");
-    // Implement logic for handling multi-line input and processing here
-    // For now, we'll simply echo the input lines.
-    while !term_now.load(std::sync::atomic::Ordering::Relaxed) {
-        for arg in args {
-            println!("{}", arg);
+// Function to generate shell completions. The commands the main configs have
+// patterns for are offered for COMMAND, as they are at generation time.
+fn generate_completions(shell: Shell, matches: &ArgMatches) -> Result<()> {
+    let commands = config::configured_commands(config_path(matches))?;
+    let mut app = cli().mut_arg("COMMAND", |arg| arg.value_parser(PossibleValuesParser::new(commands.clone())));
+    let mut script = Vec::new();
+    generate(shell, &mut app, "rcz", &mut script);
+
+    let script = add_command_candidates(shell, &String::from_utf8_lossy(&script), &commands);
+    io::stdout().write_all(script.as_bytes()).map_err(|e| RczError::io("stdout", e))
+}
+
+// The fish, elvish and PowerShell generators leave out the values of
+// positional arguments, so the commands are added to those scripts here.
+// Command names never contain quotes, so they need no escaping.
+fn add_command_candidates(shell: Shell, script: &str, commands: &[String]) -> String {
+    if commands.is_empty() {
+        return script.to_string();
+    }
+    let (anchor, candidates) = match shell {
+        Shell::Fish => {
+            let list = commands.join(" ");
+            return format!(
+                "{}complete -c rcz -n \"not __fish_seen_subcommand_from {}\" -f -a \"{}\"\n",
+                script, list, list
+            );
         }
-        std::thread::sleep(std::time::Duration::from_secs(1));
+        Shell::Elvish => (
+            "        &'rcz'= {\n",
+            commands.iter().map(|c| format!("            cand {} 'command'\n", c)).collect::<String>(),
+        ),
+        Shell::PowerShell => (
+            "        'rcz' {\n",
+            commands
+                .iter()
+                .map(|c| format!("            [CompletionResult]::new('{0}', '{0}', [CompletionResultType]::Command, '{0}')\n", c))
+                .collect::<String>(),
+        ),
+        _ => return script.to_string(),
+    };
+    match script.find(anchor) {
+        Some(at) => {
+            let at = at + anchor.len();
+            format!("{}{}{}", &script[..at], candidates, &script[at..])
+        }
+        None => script.to_string(),
     }
 }
-
-// Function to handle interactive mode
-fn run_interactive_mode() {
-    println!("Interactive mode is currently a basic placeholder implementation.");
-    // Implement the interactive configuration logic here
-    // For now, we'll just simulate interaction
-    let mut buffer = String::new();
-    println!("Enter command configuration details:");
-    std::io::stdin().read_line(&mut buffer).expect("Failed to read line");
-    println!("Received configuration: {}", buffer);
-}
\ No newline at end of file
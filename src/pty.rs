@@ -0,0 +1,188 @@
+use signal_hook::{consts::signal::SIGWINCH, iterator::{Handle, Signals}};
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::ptr;
+
+pub struct Pty {
+    pub master: File,
+    pub slave: File,
+}
+
+impl Pty {
+    // Allocates a new pseudoterminal sized like the terminal we are running in.
+    pub fn open() -> io::Result<Pty> {
+        let mut master: RawFd = -1;
+        let mut slave: RawFd = -1;
+        let mut size = terminal_size();
+        let size_ptr = size.as_mut().map_or(ptr::null_mut(), |s| s as *mut libc::winsize);
+
+        let ret = unsafe { libc::openpty(&mut master, &mut slave, ptr::null_mut(), ptr::null(), size_ptr) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let pty = unsafe {
+            Pty {
+                master: File::from_raw_fd(master),
+                slave: File::from_raw_fd(slave),
+            }
+        };
+        // The child must only ever see the slave side.
+        set_cloexec(pty.master.as_raw_fd())?;
+        Ok(pty)
+    }
+
+    // Makes the slave side the child's stdin and stdout (and stderr if
+    // requested) and its controlling terminal.
+    pub fn attach(&self, cmd: &mut Command, with_stderr: bool) -> io::Result<()> {
+        cmd.stdin(Stdio::from(self.slave.try_clone()?));
+        cmd.stdout(Stdio::from(self.slave.try_clone()?));
+        if with_stderr {
+            cmd.stderr(Stdio::from(self.slave.try_clone()?));
+        }
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    // Copies our stdin to the child through the master side, so that what
+    // it reads from its stdin or /dev/tty, such as a password, comes from
+    // ours. At the end of our input the pseudoterminal's end of file
+    // character is sent.
+    pub fn forward_input(&self) -> io::Result<()> {
+        let mut master = self.master.try_clone()?;
+        let eof = unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(master.as_raw_fd(), &mut termios) == 0 {
+                termios.c_cc[libc::VEOF]
+            } else {
+                0x04
+            }
+        };
+
+        std::thread::spawn(move || {
+            let mut stdin = io::stdin().lock();
+            let mut buf = [0; 4096];
+            let mut at_line_start = true;
+            loop {
+                match stdin.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        if master.write_all(&buf[..n]).is_err() {
+                            return;
+                        }
+                        at_line_start = buf[n - 1] == b'\n';
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                }
+            }
+            // Ending a partial line takes one end of file character and
+            // ending the input another.
+            let eofs = if at_line_start { 1 } else { 2 };
+            let _ = master.write_all(&vec![eof; eofs]);
+        });
+        Ok(())
+    }
+
+    // Keeps the pseudoterminal size in sync with our own terminal until the
+    // returned handle is closed.
+    pub fn forward_window_size(&self) -> io::Result<Handle> {
+        let master = self.master.try_clone()?;
+        let mut signals = Signals::new([SIGWINCH])?;
+        let handle = signals.handle();
+
+        std::thread::spawn(move || {
+            for _ in signals.forever() {
+                if let Some(size) = terminal_size() {
+                    unsafe {
+                        libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ as _, &size);
+                    }
+                }
+            }
+        });
+
+        Ok(handle)
+    }
+}
+
+// While it is kept, our own terminal, when stdin is one, neither echoes
+// nor edits lines: the pseudoterminal does both for the child, which would
+// otherwise see lines only once complete, and passwords would show. Keys
+// such as Ctrl-C still signal rcz, which passes the signals on.
+pub struct InputMode {
+    saved: Option<libc::termios>,
+}
+
+impl InputMode {
+    pub fn pass_through() -> InputMode {
+        if !io::stdin().is_terminal() {
+            return InputMode { saved: None };
+        }
+        unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+                return InputMode { saved: None };
+            }
+            let saved = termios;
+            termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+            termios.c_cc[libc::VMIN] = 1;
+            termios.c_cc[libc::VTIME] = 0;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &termios);
+            InputMode { saved: Some(saved) }
+        }
+    }
+}
+
+impl Drop for InputMode {
+    fn drop(&mut self) {
+        if let Some(saved) = &self.saved {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, saved);
+            }
+        }
+    }
+}
+
+// Reader over the master side that reports end of file once the child has
+// closed the slave, instead of the EIO Linux returns in that case.
+pub struct PtyReader(pub File);
+
+impl Read for PtyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => Ok(0),
+            other => other,
+        }
+    }
+}
+
+fn terminal_size() -> Option<libc::winsize> {
+    [libc::STDOUT_FILENO, libc::STDIN_FILENO, libc::STDERR_FILENO]
+        .into_iter()
+        .find_map(|fd| {
+            let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+            let ret = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ as _, &mut size) };
+            (ret == 0 && size.ws_col > 0).then_some(size)
+        })
+}
+
+fn set_cloexec(fd: RawFd) -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags == -1 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
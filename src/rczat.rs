@@ -1,123 +1,763 @@
-use clap::ArgMatches;
 use fancy_regex::Regex;
-use std::collections::HashSet;
+use log::{debug, trace};
+use std::borrow::Cow;
+use std::collections::{HashSet, VecDeque};
 use std::fs::File;
-use std::io::{self, BufRead, BufReader};
-use std::process::exit;
-use crate::data::{CgrcAttrib, CgrcColorItem, CgrcConf, CgrcConfItem, COLORS_BACK, COLORS_FORG};
-// mod data;
-// use data::{CgrcAttrib, CgrcConf, CgrcConfItem, CgrcColorItem, COLORS_ATTRS, COLORS_BACK, COLORS_FORG};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use crate::builtin;
+use crate::data::{
+    CgrcAttrib, CgrcColorDepth, CgrcColorItem, CgrcConf, CgrcConfItem, CgrcCountMode, CgrcGradient, CgrcScale,
+    CgrcValueTest, LcBackColor, LcExtColor, LcLogColor, BACK_ANSI16, COLORS_ATTRS, COLORS_BACK, COLORS_FORG, FORG_ANSI16,
+};
+use crate::error::{RczError, Result};
 
-pub fn process_rczat(matches: &ArgMatches, _args: &[String]) {
-    let binding = String::new();
-    let main_conf_file = matches.get_one::<String>("config").unwrap_or(&binding);
-    if main_conf_file.is_empty() {
-        eprintln!("No main configuration file specified.");
-        exit(1);
+// Bounds the lookahead buffer of multi-line mode.
+pub const MAX_RULE_LINES: usize = 50;
+
+// How long a partial line, or the lines multi-line mode holds back, wait for
+// more output before they are written out anyway.
+pub const FLUSH_TIMEOUT: Duration = Duration::from_millis(100);
+
+// State carried from one line to the next while colorizing a stream.
+#[derive(Default)]
+pub struct CgrcState {
+    // Colour of the block opened by a `count=block` rule, if one is open.
+    block: Option<CgrcColorItem>,
+}
+
+// Colorizes lines one at a time with a loaded configuration, keeping the
+// state that carries over from one line to the next.
+pub struct Colorizer {
+    conf: CgrcConf,
+    state: CgrcState,
+}
+
+impl Colorizer {
+    pub fn new(conf: CgrcConf) -> Colorizer {
+        Colorizer { conf, state: CgrcState::default() }
     }
 
-    let main_config = load_main_config(main_conf_file);
-    if main_config.items.is_empty() {
-        eprintln!("Main configuration file is empty or invalid.");
-        exit(1);
+    pub fn from_file(conf_file: &str) -> Result<Colorizer> {
+        Ok(Colorizer::new(load_main_config(conf_file)?))
     }
 
-    let stdin = io::stdin();
-    for line in stdin.lock().lines() {
-        match line {
-            Ok(line) => {
-                let mut processed = false;
-
-                for item in &main_config.items {
-                    if let Some(regex) = &item.regex {
-                        if let Ok(Some(captures)) = regex.captures(&line) {
-                            let colored_line = apply_colors(&line, &captures, &item.colors);
-                            println!("{}", colored_line);
-                            processed = true;
-                            if item.skip.unwrap_or(false) {
-                                break;
-                            }
-                        }
-                    }
+    pub fn conf(&self) -> &CgrcConf {
+        &self.conf
+    }
+
+    // Returns the line, without its line ending, with escape sequences added.
+    pub fn colorize(&mut self, line: &str) -> String {
+        colorize_line(line, &self.conf, &mut self.state)
+    }
+}
+
+impl FromStr for Colorizer {
+    type Err = RczError;
+
+    fn from_str(conf: &str) -> Result<Colorizer> {
+        Ok(Colorizer::new(read_config(conf.as_bytes(), "<string>")?))
+    }
+}
+
+// Colorizes a stream line by line as it is read, the same way as output
+// captured from a command.
+pub fn colorize_stream<R: BufRead, W: Write>(mut reader: R, writer: &mut W, conf: &CgrcConf) -> io::Result<()> {
+    let mut colorizer = StreamColorizer::new(conf, false);
+    loop {
+        let chunk = reader.fill_buf()?;
+        if chunk.is_empty() {
+            writer.write_all(&colorizer.flush())?;
+            return writer.flush();
+        }
+        let (text, read) = (colorizer.feed(chunk), chunk.len());
+        reader.consume(read);
+        writer.write_all(&text)?;
+        writer.flush()?;
+    }
+}
+
+pub fn colorize_line(line: &str, conf: &CgrcConf, state: &mut CgrcState) -> String {
+    let (spans, unblock) = match_spans(&[line], conf, state);
+    finish_line(line, &spans, unblock, state)
+}
+
+// A coloured byte range, in the coordinates of the text it was matched in.
+// Gradient colours are worked out for each match and so are owned.
+type Span<'a> = (usize, usize, Cow<'a, CgrcColorItem>);
+
+// Runs the rules over the first line of `window`. The other lines of the
+// window are the ones that follow it, which multi-line rules may match into;
+// the returned spans are offsets into the window's lines joined by newlines.
+// Also returns whether a `count=unblock` rule matched.
+fn match_spans<'a>(window: &[&str], conf: &'a CgrcConf, state: &mut CgrcState) -> (Vec<Span<'a>>, bool) {
+    let line = window[0];
+    let mut spans = Vec::new();
+    let mut prev_mode = CgrcCountMode::CgrcCountMore;
+    let mut prev_colors: &[Option<CgrcColorItem>] = &[];
+    let mut unblock = false;
+    trace!("line {:?}", line);
+
+    for (index, item) in conf.items.iter().enumerate() {
+        // `previous` rules take both the count and the colours of the rule before them.
+        let (mode, colors) = match &item.count_mode {
+            Some(CgrcCountMode::CgrcCountPrevious) => (prev_mode.clone(), prev_colors),
+            Some(mode) => (mode.clone(), &item.colors[..]),
+            None => (CgrcCountMode::CgrcCountMore, &item.colors[..]),
+        };
+        prev_mode = mode.clone();
+        prev_colors = colors;
+
+        let Some(regex) = &item.regex else { continue };
+        let lines = item.lines.unwrap_or(1).min(window.len());
+        let text = if lines > 1 { Cow::Owned(window[..lines].join("\n")) } else { Cow::Borrowed(line) };
+        // Only matches that start on this line belong to it; the ones that
+        // start further on are found again once their own line comes up.
+        let captures: Vec<_> = match mode {
+            CgrcCountMode::CgrcCountMore => regex
+                .captures_iter(&text)
+                .filter_map(|caps| caps.ok())
+                .take_while(|caps| caps.get(0).is_some_and(|m| m.start() <= line.len()))
+                .collect(),
+            _ => regex
+                .captures(&text)
+                .ok()
+                .flatten()
+                .filter(|caps| caps.get(0).is_some_and(|m| m.start() <= line.len()))
+                .into_iter()
+                .collect(),
+        };
+        if captures.is_empty() {
+            trace!("  rule {} /{}/: no match", index + 1, regex.as_str());
+            continue;
+        }
+        trace!("  rule {} /{}/: matched {}", index + 1, regex.as_str(), describe_captures(&captures));
+
+        match mode {
+            CgrcCountMode::CgrcCountBlock => state.block = colors.first().cloned().flatten(),
+            CgrcCountMode::CgrcCountUnblock => unblock = true,
+            _ => {
+                for caps in &captures {
+                    apply_colors(caps, colors, item, &mut spans);
                 }
+            }
+        }
+
+        if mode == CgrcCountMode::CgrcCountStop {
+            trace!("  rule {} has count=stop, so no further rules are tried", index + 1);
+            break;
+        }
+        if item.skip.unwrap_or(false) {
+            trace!("  rule {} has skip=yes, so no further rules are tried", index + 1);
+            break;
+        }
+    }
+
+    (spans, unblock)
+}
+
+// The byte ranges of each match and its groups, such as `0:4..9 1:4..6`, for
+// the trace log.
+fn describe_captures(captures: &[fancy_regex::Captures]) -> String {
+    let matches: Vec<String> = captures
+        .iter()
+        .map(|caps| {
+            let groups: Vec<String> = caps
+                .iter()
+                .enumerate()
+                .filter_map(|(i, group)| group.map(|g| format!("{}:{}..{}", i, g.start(), g.end())))
+                .collect();
+            groups.join(" ")
+        })
+        .collect();
+    matches.join(", ")
+}
+
+// Renders a line with its spans and updates the block state.
+fn finish_line(line: &str, spans: &[Span], unblock: bool, state: &mut CgrcState) -> String {
+    // Lines inside a block, including the lines that open and close it, get
+    // the block colour as a base that the rules then paint over.
+    let result = render_line(line, state.block.as_ref(), spans);
+    if unblock {
+        state.block = None;
+    }
 
-                if !processed {
-                    println!("{}", line);
+    result
+}
+
+// Multi-line mode: lines are held back, as many as the longest `lines=` of
+// the profile, so that multi-line rules can match across them. The parts of
+// a match that fall on later lines are painted there before that line's own
+// matches.
+pub struct MultilineColorizer<'a> {
+    conf: &'a CgrcConf,
+    state: CgrcState,
+    lookahead: usize,
+    // Held back lines with their line endings and the spans carried to them.
+    pending: VecDeque<(String, &'static str, Vec<Span<'a>>)>,
+}
+
+impl<'a> MultilineColorizer<'a> {
+    pub fn new(conf: &'a CgrcConf) -> MultilineColorizer<'a> {
+        let lookahead = conf.items.iter().filter_map(|item| item.lines).max().unwrap_or(1);
+        MultilineColorizer { conf, state: CgrcState::default(), lookahead, pending: VecDeque::new() }
+    }
+
+    // Takes the next line, without its ending, and returns the oldest held
+    // back line once enough lines follow it.
+    pub fn push(&mut self, line: String, ending: &'static str) -> Option<String> {
+        self.pending.push_back((line, ending, Vec::new()));
+        if self.pending.len() >= self.lookahead {
+            self.next_line()
+        } else {
+            None
+        }
+    }
+
+    // Returns all held back lines, without waiting for the lines after them.
+    pub fn flush(&mut self) -> String {
+        let mut result = String::new();
+        while let Some(line) = self.next_line() {
+            result += &line;
+        }
+        result
+    }
+
+    fn next_line(&mut self) -> Option<String> {
+        let window: Vec<&str> = self.pending.iter().map(|(line, _, _)| line.as_str()).collect();
+        if window.is_empty() {
+            return None;
+        }
+        let (spans, unblock) = match_spans(&window, self.conf, &mut self.state);
+
+        // Hand each span to the lines it covers, in their own offsets.
+        let mut offset = 0;
+        for (line, _, carried) in self.pending.iter_mut() {
+            for (start, end, color) in &spans {
+                let (start, end) = ((*start).max(offset), (*end).min(offset + line.len()));
+                if start < end {
+                    carried.push((start - offset, end - offset, color.clone()));
                 }
             }
-            Err(e) => eprintln!("Error reading line: {}", e),
+            offset += line.len() + 1;
         }
+
+        let (line, ending, spans) = self.pending.pop_front()?;
+        Some(finish_line(&line, &spans, unblock, &mut self.state) + ending)
     }
 }
 
-fn load_main_config(conf_file: &str) -> CgrcConf {
-    let file = File::open(conf_file).unwrap_or_else(|e| {
-        eprintln!("Failed to open main configuration file: {}. Error: {}", conf_file, e);
-        exit(1);
-    });
-    let reader = BufReader::new(file);
-    let mut conf = CgrcConf::new();
-    let mut current_item = CgrcConfItem::new();
+// Colorizes output that arrives in chunks of any size, for callers that do
+// their own reading. Complete lines are colorized as they come in, unless
+// multi-line mode holds them back; `flush` writes out whatever is left.
+pub struct StreamColorizer<'a> {
+    lines: MultilineColorizer<'a>,
+    partial: Vec<u8>,
+}
 
-    for line in reader.lines() {
-        match line {
-            Ok(line) => parse_config_line(&line.trim(), &mut current_item, &mut conf),
+impl<'a> StreamColorizer<'a> {
+    pub fn new(conf: &'a CgrcConf, multiline: bool) -> StreamColorizer<'a> {
+        let mut lines = MultilineColorizer::new(conf);
+        if !multiline {
+            lines.lookahead = 1;
+        }
+        StreamColorizer { lines, partial: Vec::new() }
+    }
+
+    // Whether nothing is waiting to be written out.
+    pub fn is_idle(&self) -> bool {
+        self.lines.pending.is_empty() && self.partial.is_empty()
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.partial.extend_from_slice(chunk);
+        let mut result = Vec::new();
+        while let Some(newline) = self.partial.iter().position(|&b| b == b'\n') {
+            let mut line: Vec<u8> = self.partial.drain(..=newline).collect();
+            line.pop();
+            // Keep CRLF endings (as produced by a pty) out of the matched text.
+            let ending = if line.last() == Some(&b'\r') {
+                line.pop();
+                "\r\n"
+            } else {
+                "\n"
+            };
+            self.push(line, ending, &mut result);
+        }
+        result
+    }
+
+    // Returns the held back lines and the partial line, if any. Output that
+    // continues the partial line later is colorized as a line of its own.
+    pub fn flush(&mut self) -> Vec<u8> {
+        let mut result = Vec::new();
+        if !self.partial.is_empty() {
+            let line = std::mem::take(&mut self.partial);
+            self.push(line, "", &mut result);
+        }
+        result.extend_from_slice(self.lines.flush().as_bytes());
+        result
+    }
+
+    // Lines that are not UTF-8 are written out as they are. No match can
+    // reach across them, so the lines held back before them are let go.
+    fn push(&mut self, line: Vec<u8>, ending: &'static str, result: &mut Vec<u8>) {
+        match String::from_utf8(line) {
+            Ok(line) => {
+                if let Some(done) = self.lines.push(line, ending) {
+                    result.extend_from_slice(done.as_bytes());
+                }
+            }
             Err(e) => {
-                eprintln!("Error reading configuration file: {}", e);
-                exit(1);
+                result.extend_from_slice(self.lines.flush().as_bytes());
+                result.extend_from_slice(e.as_bytes());
+                result.extend_from_slice(ending.as_bytes());
             }
         }
     }
+}
+
+// Like colorize_stream, but for multi-line rules. Lines are read on another
+// thread so that, when no more output arrives within `flush_timeout`, the
+// held back lines and any partial line are written out without waiting.
+pub fn colorize_stream_multiline<R, W>(mut reader: R, writer: &mut W, conf: &CgrcConf, flush_timeout: Duration) -> io::Result<()>
+where
+    R: Read + Send + 'static,
+    W: Write,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = [0; 8192];
+        loop {
+            let result = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => Ok(buf[..n].to_vec()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            let failed = result.is_err();
+            if sender.send(result).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let mut colorizer = StreamColorizer::new(conf, true);
+    loop {
+        let chunk = if colorizer.is_idle() {
+            receiver.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected)
+        } else {
+            receiver.recv_timeout(flush_timeout)
+        };
+        match chunk {
+            Ok(chunk) => writer.write_all(&colorizer.feed(&chunk?))?,
+            Err(mpsc::RecvTimeoutError::Timeout) => writer.write_all(&colorizer.flush())?,
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                writer.write_all(&colorizer.flush())?;
+                return writer.flush();
+            }
+        }
+        writer.flush()?;
+    }
+}
+
+pub fn load_main_config(conf_file: &str) -> Result<CgrcConf> {
+    debug!("loading profile {}", conf_file);
+    if let Some(contents) = builtin::builtin_file(conf_file) {
+        return read_config(contents.as_bytes(), conf_file);
+    }
+    let file = File::open(conf_file).map_err(|e| RczError::io(conf_file, e))?;
+    read_config(BufReader::new(file), conf_file)
+}
+
+// Parses a configuration from any reader; `conf_file` is only used to
+// locate errors.
+pub fn read_config<R: BufRead>(reader: R, conf_file: &str) -> Result<CgrcConf> {
+    let mut conf = CgrcConf::new();
+    let mut current_item = CgrcConfItem::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| RczError::io(conf_file, e))?;
+        parse_config_line(line.trim(), &mut current_item, &mut conf, conf_file, index + 1)?;
+    }
 
     if current_item.regex.is_some() {
-        conf.items.push(current_item);
+        push_item(&mut conf, current_item);
     }
 
-    conf
+    Ok(conf)
 }
 
-fn parse_config_line(line: &str, current_item: &mut CgrcConfItem, conf: &mut CgrcConf) {
+fn parse_config_line(
+    line: &str,
+    current_item: &mut CgrcConfItem,
+    conf: &mut CgrcConf,
+    conf_file: &str,
+    line_no: usize,
+) -> Result<()> {
     if line.is_empty() || line.starts_with('#') {
-        return;
+        return Ok(());
     }
 
     if line.starts_with("regexp=") {
         if current_item.regex.is_some() {
-            conf.items.push(current_item.clone());
-            *current_item = CgrcConfItem::new();
+            push_item(conf, std::mem::take(current_item));
         }
-        current_item.regex = Some(Regex::new(&line.replacen("regexp=", "", 1)).unwrap_or_else(|e| {
-            eprintln!("Invalid regex pattern: {}. Error: {}", line, e);
-            exit(1);
-        }));
+        current_item.regex = Some(Regex::new(&line.replacen("regexp=", "", 1)).map_err(|e| RczError::regex(conf_file, line_no, e))?);
     } else if line.starts_with("colours=") {
         let colors = line.replacen("colours=", "", 1);
         current_item.colors = parse_colors(&colors);
+    } else if line.starts_with("count=") {
+        let mode = line.replacen("count=", "", 1);
+        current_item.count_mode = Some(
+            parse_count_mode(&mode)
+                .ok_or_else(|| RczError::config(conf_file, line_no, format!("invalid count mode `{}`", mode)))?,
+        );
+    } else if line.starts_with("lines=") {
+        let lines = line.replacen("lines=", "", 1);
+        current_item.lines = Some(
+            lines
+                .trim()
+                .parse()
+                .ok()
+                .filter(|n| (1..=MAX_RULE_LINES).contains(n))
+                .ok_or_else(|| {
+                    RczError::config(conf_file, line_no, format!("`lines` must be from 1 to {}, not `{}`", MAX_RULE_LINES, lines))
+                })?,
+        );
     } else if line.starts_with("skip=") {
         current_item.skip = Some(line.replacen("skip=", "", 1) == "yes");
+    } else if let Some((group, colors)) = line.strip_prefix("colours.").and_then(|rest| rest.split_once('=')) {
+        let index = group_index(current_item.regex.as_ref(), "colours", group)
+            .and_then(|index| single_color(group, colors).map(|_| index))
+            .map_err(|message| RczError::config(conf_file, line_no, message))?;
+        let color = parse_colors(colors).into_iter().next().flatten();
+        current_item.named_colors.push((index, color));
+    } else if let Some((group, entries)) = line.strip_prefix("values.").and_then(|rest| rest.split_once('=')) {
+        let index = group_index(current_item.regex.as_ref(), "values", group)
+            .map_err(|message| RczError::config(conf_file, line_no, message))?;
+        let entries = split_value_entries(entries).map_err(|message| RczError::config(conf_file, line_no, message))?;
+        let entries = entries
+            .into_iter()
+            .map(|(test, color)| (test, parse_colors(color).into_iter().next().flatten()))
+            .collect();
+        current_item.value_colors.push((index, entries));
+    } else if let Some((group, stops)) = line.strip_prefix("gradient.").and_then(|rest| rest.split_once('=')) {
+        let index = group_index(current_item.regex.as_ref(), "gradient", group)
+            .map_err(|message| RczError::config(conf_file, line_no, message))?;
+        let gradient = parse_gradient(stops).map_err(|message| RczError::config(conf_file, line_no, message))?;
+        current_item.gradients.push((index, gradient));
     } else if line == "-" {
-        conf.items.push(current_item.clone());
-        *current_item = CgrcConfItem::new();
+        push_item(conf, std::mem::take(current_item));
+    }
+
+    Ok(())
+}
+
+// Finds the group, by number or by name, that a `colours.GROUP=` or
+// `values.GROUP=` line is for. The rule's regexp has to come before it.
+pub(crate) fn group_index(regex: Option<&Regex>, key: &str, group: &str) -> std::result::Result<usize, String> {
+    let regex = regex.ok_or_else(|| format!("`{}.{}` comes before the rule's regexp", key, group))?;
+    if let Ok(index) = group.parse::<usize>() {
+        return match index < regex.captures_len() {
+            true => Ok(index),
+            false => Err(format!("the regexp has no group {}", index)),
+        };
     }
+    regex
+        .capture_names()
+        .position(|name| name == Some(group))
+        .ok_or_else(|| format!("the regexp has no group named `{}`", group))
 }
 
-fn parse_colors(colors_str: &str) -> Vec<CgrcColorItem> {
-    colors_str.split(',').map(|color| {
-        let attr_set: HashSet<CgrcAttrib> = HashSet::new();
-        let forg = COLORS_FORG.get(color.trim()).cloned().unwrap_or(COLORS_FORG["default"]);
-        let back = COLORS_BACK.get("on_default").cloned().unwrap_or(COLORS_BACK["on_default"]);
-        CgrcColorItem::new(attr_set, forg, back)
-    }).collect()
+// Parses a gradient= list such as `log, 1 #00ff00, 100 #ffff00, 1000 #ff0000`:
+// an optional `linear` or `log` scale, then two or more stops in increasing
+// order, each a value and a colour. `on_` colours make a background gradient.
+pub(crate) fn parse_gradient(stops: &str) -> std::result::Result<CgrcGradient, String> {
+    let mut scale = CgrcScale::CgrcScaleLinear;
+    let mut parsed: Vec<(f64, (u8, u8, u8))> = Vec::new();
+    let mut back = None;
+
+    for entry in split_outside_parens(stops, |c| c == ',').into_iter().map(str::trim) {
+        match entry {
+            "linear" if parsed.is_empty() => scale = CgrcScale::CgrcScaleLinear,
+            "log" if parsed.is_empty() => scale = CgrcScale::CgrcScaleLog,
+            _ => {
+                let (value, color) = entry.split_once(char::is_whitespace).unwrap_or((entry, ""));
+                let value: f64 = value.parse().map_err(|_| format!("invalid gradient stop `{}`", entry))?;
+                let (is_back, color) =
+                    parse_ext_color(color.trim()).ok_or_else(|| format!("invalid gradient colour in `{}`", entry))?;
+                if *back.get_or_insert(is_back) != is_back {
+                    return Err("gradient stops mix text and background colours".to_string());
+                }
+                if parsed.last().is_some_and(|&(previous, _)| value <= previous) {
+                    return Err(format!("gradient stop `{}` does not come after the one before it", entry));
+                }
+                if scale == CgrcScale::CgrcScaleLog && value <= 0.0 {
+                    return Err(format!("logarithmic gradient stop `{}` is not above 0", entry));
+                }
+                parsed.push((value, color.to_rgb()));
+            }
+        }
+    }
+
+    if parsed.len() < 2 {
+        return Err("a gradient needs at least two stops".to_string());
+    }
+    Ok(CgrcGradient { scale, stops: parsed, back: back.unwrap_or(false), depth: CgrcColorDepth::detect() })
+}
+
+// A `colours.GROUP=` line colours a single group, so it takes one entry.
+pub(crate) fn single_color(group: &str, colors: &str) -> std::result::Result<(), String> {
+    match color_count(colors) {
+        1 => Ok(()),
+        n => Err(format!("`colours.{}` takes one colour, not {}", group, n)),
+    }
+}
+
+// Splits a values= list such as `<50 green, <200 yellow, else red` into its
+// tests and the colours that go with them.
+pub(crate) fn split_value_entries(entries: &str) -> std::result::Result<Vec<(CgrcValueTest, &str)>, String> {
+    split_outside_parens(entries, |c| c == ',')
+        .into_iter()
+        .map(|entry| {
+            let entry = entry.trim();
+            let (test, color) = entry.split_once(char::is_whitespace).unwrap_or((entry, ""));
+            if color.trim().is_empty() {
+                return Err(format!("`{}` has no colour", entry));
+            }
+            let threshold = |limit: &str| limit.trim().parse::<f64>().map_err(|_| format!("invalid threshold `{}`", test));
+            let test = if let Some(limit) = test.strip_prefix("<=") {
+                CgrcValueTest::LessEq(threshold(limit)?)
+            } else if let Some(limit) = test.strip_prefix(">=") {
+                CgrcValueTest::GreaterEq(threshold(limit)?)
+            } else if let Some(limit) = test.strip_prefix('<') {
+                CgrcValueTest::Less(threshold(limit)?)
+            } else if let Some(limit) = test.strip_prefix('>') {
+                CgrcValueTest::Greater(threshold(limit)?)
+            } else if test == "else" {
+                CgrcValueTest::Else
+            } else {
+                CgrcValueTest::Pattern(test.to_string())
+            };
+            Ok((test, color.trim()))
+        })
+        .collect()
+}
+
+// Adds a finished rule, with its named colours in place among the positional
+// ones.
+fn push_item(conf: &mut CgrcConf, mut item: CgrcConfItem) {
+    for (index, color) in std::mem::take(&mut item.named_colors) {
+        if item.colors.len() <= index {
+            item.colors.resize(index + 1, None);
+        }
+        item.colors[index] = color;
+    }
+    conf.items.push(item);
+}
+
+pub(crate) fn parse_count_mode(mode: &str) -> Option<CgrcCountMode> {
+    match mode.trim() {
+        "once" => Some(CgrcCountMode::CgrcCountOnce),
+        "more" => Some(CgrcCountMode::CgrcCountMore),
+        "stop" => Some(CgrcCountMode::CgrcCountStop),
+        "previous" => Some(CgrcCountMode::CgrcCountPrevious),
+        "block" => Some(CgrcCountMode::CgrcCountBlock),
+        "unblock" => Some(CgrcCountMode::CgrcCountUnblock),
+        _ => None,
+    }
 }
 
-fn apply_colors(line: &str, captures: &fancy_regex::Captures, colors: &[CgrcColorItem]) -> String {
-    let mut result = line.to_string();
+// Parses a colours= list. An entry that is empty or only says `unchanged`
+// or `none` is kept as `None`, so that the entries stay aligned with the
+// groups they colour.
+fn parse_colors(colors_str: &str) -> Vec<Option<CgrcColorItem>> {
+    let depth = CgrcColorDepth::detect();
+    split_outside_parens(colors_str, |c| c == ',')
+        .into_iter()
+        .map(|color| {
+            let unchanged = color.split_whitespace().all(|token| matches!(token, "unchanged" | "none"));
+            (!unchanged).then(|| parse_color_item(color, depth))
+        })
+        .collect()
+}
+
+// The number of entries in a colours= value.
+pub(crate) fn color_count(colors_str: &str) -> usize {
+    split_outside_parens(colors_str, |c| c == ',').len()
+}
+
+// The words of a colours= value that are not colour or attribute names, and
+// that parse_color_item therefore ignores.
+pub(crate) fn unknown_color_tokens(colors_str: &str) -> Vec<&str> {
+    split_outside_parens(colors_str, |c| c == ',' || c.is_whitespace())
+        .into_iter()
+        .filter(|token| {
+            !token.is_empty()
+                && !COLORS_ATTRS.contains_key(token)
+                && !COLORS_FORG.contains_key(token)
+                && !COLORS_BACK.contains_key(token)
+                && parse_ext_color(token).is_none()
+        })
+        .collect()
+}
+
+// Parses one colours= entry, a space separated mix of attributes, a
+// foreground and a background such as `bold underline red on_blue` or
+// `italic #ff8800 on_color22`.
+fn parse_color_item(color: &str, depth: CgrcColorDepth) -> CgrcColorItem {
+    let mut attr_set: HashSet<CgrcAttrib> = HashSet::new();
+    let mut forg = LcLogColor::LcForgColDefault;
+    let mut back = LcBackColor::LcBackColDefault;
+    let mut forg_ext = None;
+    let mut back_ext = None;
+
+    for token in split_outside_parens(color, char::is_whitespace).into_iter().filter(|t| !t.is_empty()) {
+        if let Some(attr) = COLORS_ATTRS.get(token) {
+            // `none`, `unchanged` and `default` leave the default colours in place.
+            if !matches!(attr, CgrcAttrib::CgrcNone | CgrcAttrib::CgrcReset) {
+                attr_set.insert(*attr);
+            }
+        } else if let Some(color) = COLORS_FORG.get(token) {
+            forg = *color;
+            forg_ext = None;
+        } else if let Some(color) = COLORS_BACK.get(token) {
+            back = *color;
+            back_ext = None;
+        } else if let Some((is_back, ext)) = parse_ext_color(token) {
+            match (is_back, ext.downsample(depth)) {
+                (false, Ok(ext)) => forg_ext = Some(ext),
+                (false, Err(index)) => (forg, forg_ext) = (FORG_ANSI16[index], None),
+                (true, Ok(ext)) => back_ext = Some(ext),
+                (true, Err(index)) => (back, back_ext) = (BACK_ANSI16[index], None),
+            }
+        }
+    }
+
+    CgrcColorItem::new_ext(attr_set, forg, back, forg_ext, back_ext)
+}
+
+// Parses `color196`, `#ff8800` or `rgb(255,136,0)`, each optionally prefixed
+// with `on_` for a background. Returns whether it is a background.
+fn parse_ext_color(token: &str) -> Option<(bool, LcExtColor)> {
+    let (is_back, spec) = match token.strip_prefix("on_") {
+        Some(spec) => (true, spec),
+        None => (false, token),
+    };
+
+    let color = if let Some(index) = spec.strip_prefix("color") {
+        LcExtColor::LcExtIndexed(index.parse().ok()?)
+    } else if let Some(hex) = spec.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        LcExtColor::LcExtRgb(channel(0)?, channel(2)?, channel(4)?)
+    } else if let Some(args) = spec.strip_prefix("rgb(").and_then(|rest| rest.strip_suffix(')')) {
+        let channels: Vec<u8> = args.split(',').map(|c| c.trim().parse().ok()).collect::<Option<_>>()?;
+        match channels[..] {
+            [r, g, b] => LcExtColor::LcExtRgb(r, g, b),
+            _ => return None,
+        }
+    } else {
+        return None;
+    };
+
+    Some((is_back, color))
+}
+
+// Splits on separators that are not inside parentheses, so that `rgb(1,2,3)`
+// survives splitting a colours= list on commas.
+fn split_outside_parens(s: &str, is_separator: impl Fn(char) -> bool) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            _ if depth == 0 && is_separator(c) => {
+                pieces.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    pieces.push(&s[start..]);
+    pieces
+}
+
+// Records the colour of every capture of one match as a byte span. Colour N
+// goes to group N, group 0 first so that the inner groups painted after it
+// take precedence. A values= test the captured text passes, or else a
+// gradient its number falls on, picks another colour. Groups without a
+// colour, or with an `unchanged` one, are left as they are.
+fn apply_colors<'a>(
+    captures: &fancy_regex::Captures,
+    colors: &'a [Option<CgrcColorItem>],
+    item: &'a CgrcConfItem,
+    spans: &mut Vec<Span<'a>>,
+) {
     for (i, capture) in captures.iter().enumerate() {
-        if let Some(matched) = capture {
-            let color_item = colors.get(i).unwrap_or(&colors[0]);
-            let colored = format!("{}{}{}", color_item.escape_seq, matched.as_str(), color_item.clear_seq);
-            result = result.replace(matched.as_str(), &colored);
+        let Some(matched) = capture.filter(|m| m.start() < m.end()) else { continue };
+        let text = matched.as_str();
+        let by_value = item
+            .value_colors
+            .iter()
+            .filter(|(group, _)| *group == i)
+            .find_map(|(_, entries)| entries.iter().find(|(test, _)| test.matches(text)));
+        let color_item = match by_value {
+            Some((_, color_item)) => color_item.as_ref().map(Cow::Borrowed),
+            None => item
+                .gradients
+                .iter()
+                .filter(|(group, _)| *group == i)
+                .find_map(|(_, gradient)| gradient.color_for(text))
+                .map(Cow::Owned)
+                .or_else(|| colors.get(i).and_then(Option::as_ref).map(Cow::Borrowed)),
+        };
+        if let Some(color_item) = color_item {
+            spans.push((matched.start(), matched.end(), color_item));
         }
     }
+}
+
+// Paints the spans in order over the line, later spans winning, and emits an
+// escape sequence wherever the resulting colour changes.
+fn render_line(line: &str, base: Option<&CgrcColorItem>, spans: &[Span]) -> String {
+    if base.is_none() && spans.is_empty() {
+        return line.to_string();
+    }
+
+    let mut color_map = vec![base; line.len()];
+    for (start, end, color_item) in spans {
+        color_map[*start..*end].fill(Some(color_item));
+    }
+
+    let mut result = String::with_capacity(line.len() * 2);
+    let mut current: Option<&CgrcColorItem> = None;
+    for (i, ch) in line.char_indices() {
+        // A plain default colour still hides the colours painted beneath it,
+        // but is written as no colour at all.
+        let wanted = color_map[i].filter(|c| !c.is_plain());
+        if wanted.map(|c| &c.escape_seq) != current.map(|c| &c.escape_seq) {
+            if let Some(previous) = current {
+                result += &previous.clear_seq;
+            }
+            if let Some(next) = wanted {
+                result += &next.escape_seq;
+            }
+            current = wanted;
+        }
+        result.push(ch);
+    }
+    if let Some(previous) = current {
+        result += &previous.clear_seq;
+    }
+
     result
-}
\ No newline at end of file
+}
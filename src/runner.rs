@@ -1,36 +1,212 @@
 use clap::ArgMatches;
-use std::process::{Command, exit};
-use std::sync::{Arc, atomic::AtomicBool};
+use std::io::{self, IsTerminal, Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+use rustcolorizer::config::{self, ColourMode};
+use rustcolorizer::data::CgrcConf;
+use rustcolorizer::error::{RczError, Result};
+use rustcolorizer::rczat::{self, StreamColorizer};
+use crate::pty::{InputMode, Pty, PtyReader};
+use crate::signal_handler::SignalForwarder;
 
-pub fn execute_command(args: &[String], config_file: &str, matches: &ArgMatches, term_now: Arc<AtomicBool>) -> i32 {
+// How long output may stay quiet after the command exited before rcz stops
+// waiting for it to end.
+const EXIT_GRACE: Duration = Duration::from_millis(200);
+
+// Runs the command with its output colorized and returns the exit code for
+// rcz: the command's own, or 128 plus the signal that ended it.
+pub fn execute_command(args: &[String], config_file: &str, mode: ColourMode, matches: &ArgMatches) -> Result<i32> {
     let command = &args[0];
     let command_args = &args[1..];
 
-    let mut child = Command::new(command)
-        .args(command_args)
-        .spawn()
-        .expect("Failed to start command");
-
-    while !term_now.load(std::sync::atomic::Ordering::Relaxed) {
-        match child.try_wait() {
-            Ok(Some(status)) => return status.code().unwrap_or(1),
-            Ok(None) => std::thread::sleep(std::time::Duration::from_millis(100)),
-            Err(e) => {
-                eprintln!("Failed to wait on child process: {}", e);
-                return 1;
+    let conf = if config_file.is_empty() {
+        CgrcConf::new()
+    } else {
+        rczat::load_main_config(config_file)?
+    };
+
+    // Like grc: stdout is colorized by default, -e switches to stderr only,
+    // and -s brings stdout back in alongside it. Streams that --colour rules
+    // out are passed through untouched, and so is all output of a command
+    // without a profile, which keeps its terminal that way.
+    let redirect_stderr = matches.get_flag("stderr");
+    let has_profile = !config_file.is_empty();
+    let colorize_stdout = has_profile
+        && (matches.get_flag("stdout") || !redirect_stderr)
+        && config::use_colour(mode, io::stdout().is_terminal());
+    let colorize_stderr = has_profile && redirect_stderr && config::use_colour(mode, io::stderr().is_terminal());
+    let multiline = matches.get_flag("multiline");
+
+    let mut cmd = Command::new(command);
+    cmd.args(command_args);
+
+    // In pty mode the child writes to the slave side of a pseudoterminal, so
+    // stdout (and stderr with -e) reach us merged through the master side.
+    let pty = if matches.get_flag("pty") {
+        Some(Pty::open().map_err(|e| RczError::io("failed to allocate pseudoterminal", e))?)
+    } else {
+        None
+    };
+    if let Some(pty) = &pty {
+        pty.attach(&mut cmd, redirect_stderr).map_err(|e| RczError::io("failed to attach pseudoterminal", e))?;
+    } else {
+        if colorize_stdout {
+            cmd.stdout(Stdio::piped());
+        }
+        if colorize_stderr {
+            cmd.stderr(Stdio::piped());
+        }
+    }
+    let forwarder = SignalForwarder::new().map_err(|e| RczError::io("failed to set up signal handling", e))?;
+    let mut child = cmd.spawn().map_err(|source| RczError::Spawn { command: command.clone(), source })?;
+    // In pty mode the child starts a session, and so a process group, of its own.
+    let mut handles = vec![forwarder.start(child.id(), pty.is_some())];
+    // Release our copies of the slave so that reading the master ends when
+    // the child exits.
+    drop(cmd);
+
+    // Output is read on a thread per stream and the exit is waited for on
+    // another, and all of them report to the loop below, which colorizes and
+    // writes everything out.
+    let (sender, events) = mpsc::channel();
+    let empty_conf = CgrcConf::new();
+    let mut streams = Vec::new();
+    let mut input_mode = None;
+    if let Some(pty) = pty {
+        handles.extend(pty.forward_window_size().ok());
+        input_mode = Some(InputMode::pass_through());
+        pty.forward_input().map_err(|e| RczError::io("failed to forward input to the pseudoterminal", e))?;
+        let pty_conf = if colorize_stdout || colorize_stderr { &conf } else { &empty_conf };
+        streams.push(Stream::new(pty_conf, multiline, Box::new(io::stdout())));
+        spawn_reader(streams.len() - 1, PtyReader(pty.master), sender.clone());
+    }
+    if let Some(child_stdout) = child.stdout.take() {
+        streams.push(Stream::new(&conf, multiline, Box::new(io::stdout())));
+        spawn_reader(streams.len() - 1, child_stdout, sender.clone());
+    }
+    if let Some(child_stderr) = child.stderr.take() {
+        streams.push(Stream::new(&conf, multiline, Box::new(io::stderr())));
+        spawn_reader(streams.len() - 1, child_stderr, sender.clone());
+    }
+    thread::spawn(move || {
+        let _ = sender.send(Event::Exited(child.wait()));
+    });
+
+    let status = supervise(&events, &mut streams);
+    drop(input_mode);
+    for handle in handles {
+        handle.close();
+    }
+    let status = status.map_err(|e| RczError::io("failed to wait on child process", e))?;
+    Ok(status.code().unwrap_or_else(|| 128 + status.signal().unwrap_or(0)))
+}
+
+enum Event {
+    // A chunk of output from the stream with this index; empty at its end.
+    Output(usize, io::Result<Vec<u8>>),
+    Exited(io::Result<ExitStatus>),
+}
+
+// A captured output stream on its way to our own stdout or stderr.
+struct Stream<'a> {
+    colorizer: StreamColorizer<'a>,
+    sink: Box<dyn Write>,
+    open: bool,
+    // Set once writing fails; the output is then read and dropped so that the
+    // command is not blocked on a full pipe.
+    broken: bool,
+}
+
+impl<'a> Stream<'a> {
+    fn new(conf: &'a CgrcConf, multiline: bool, sink: Box<dyn Write>) -> Stream<'a> {
+        Stream { colorizer: StreamColorizer::new(conf, multiline), sink, open: true, broken: false }
+    }
+
+    fn write(&mut self, text: &[u8]) {
+        if self.broken || text.is_empty() {
+            return;
+        }
+        if let Err(e) = self.sink.write_all(text).and_then(|_| self.sink.flush()) {
+            if e.kind() != io::ErrorKind::BrokenPipe {
+                eprintln!("Error writing command output: {}", e);
             }
+            self.broken = true;
         }
     }
+}
 
-    // If a termination signal was received, terminate the child process
-    match child.kill() {
-        Ok(_) => {
-            println!("Command terminated due to signal.");
-            1
+// Waits for the command to exit and for its output to end, writing output out
+// as it arrives. Partial lines are written once no more output follows
+// within the flush timeout. Should something the command started in the
+// background keep its output open, rcz stops once that has been quiet for
+// EXIT_GRACE after the command exited.
+fn supervise(events: &Receiver<Event>, streams: &mut [Stream]) -> io::Result<ExitStatus> {
+    let mut status = None;
+    loop {
+        if status.is_some() && streams.iter().all(|stream| !stream.open) {
+            break;
         }
-        Err(e) => {
-            eprintln!("Failed to terminate command: {}", e);
-            1
+        let waiting = streams.iter().any(|stream| !stream.colorizer.is_idle());
+        let timeout = match (waiting, status.is_some()) {
+            (true, _) => Some(rczat::FLUSH_TIMEOUT),
+            (false, true) => Some(EXIT_GRACE),
+            (false, false) => None,
+        };
+        let event = match timeout {
+            Some(timeout) => events.recv_timeout(timeout),
+            None => events.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match event {
+            Ok(Event::Output(index, Ok(chunk))) if !chunk.is_empty() => {
+                let stream = &mut streams[index];
+                let text = stream.colorizer.feed(&chunk);
+                stream.write(&text);
+            }
+            Ok(Event::Output(index, result)) => {
+                if let Err(e) = result {
+                    eprintln!("Error reading command output: {}", e);
+                }
+                let stream = &mut streams[index];
+                let text = stream.colorizer.flush();
+                stream.write(&text);
+                stream.open = false;
+            }
+            Ok(Event::Exited(result)) => status = Some(result?),
+            Err(RecvTimeoutError::Timeout) if waiting => {
+                for stream in streams.iter_mut() {
+                    let text = stream.colorizer.flush();
+                    stream.write(&text);
+                }
+            }
+            Err(_) => break,
         }
     }
-}
\ No newline at end of file
+
+    // Write out what is left of streams that are still open.
+    for stream in streams.iter_mut() {
+        let text = stream.colorizer.flush();
+        stream.write(&text);
+    }
+    status.ok_or_else(|| io::Error::other("lost track of the command"))
+}
+
+fn spawn_reader<R: Read + Send + 'static>(index: usize, mut source: R, sender: Sender<Event>) {
+    thread::spawn(move || {
+        let mut buf = [0; 8192];
+        loop {
+            let result = match source.read(&mut buf) {
+                Ok(n) => Ok(buf[..n].to_vec()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            let done = !matches!(&result, Ok(chunk) if !chunk.is_empty());
+            if sender.send(Event::Output(index, result)).is_err() || done {
+                break;
+            }
+        }
+    });
+}
@@ -1,13 +1,68 @@
-use std::sync::{Arc, atomic::AtomicBool};
-use signal_hook::{consts::signal::SIGINT, iterator::Signals};
-
-pub fn setup_signal_handler(term_now: Arc<AtomicBool>) {
-    let mut signals = Signals::new(&[SIGINT]).expect("Error setting up signal handler");
-
-    std::thread::spawn(move || {
-        for _ in signals.forever() {
-            term_now.store(true, std::sync::atomic::Ordering::Relaxed);
-            println!("Interrupt signal received, stopping execution...");
-        }
-    });
-}
\ No newline at end of file
+use signal_hook::consts::signal::{
+    SIGCONT, SIGHUP, SIGINT, SIGQUIT, SIGSTOP, SIGTERM, SIGTSTP, SIGTTIN, SIGTTOU, SIGWINCH,
+};
+use signal_hook::iterator::{exfiltrator::WithOrigin, Handle, SignalsInfo};
+use signal_hook::low_level::{self, siginfo::Cause};
+use std::io;
+
+const FORWARDED: [libc::c_int; 9] = [SIGINT, SIGTERM, SIGHUP, SIGQUIT, SIGTSTP, SIGTTIN, SIGTTOU, SIGCONT, SIGWINCH];
+
+// The signals that stop a job.
+const STOPPING: [libc::c_int; 3] = [SIGTSTP, SIGTTIN, SIGTTOU];
+
+// Passes the signals rcz receives on to the command it runs, so that rcz
+// itself only ever ends when the command does.
+pub struct SignalForwarder {
+    signals: SignalsInfo<WithOrigin>,
+}
+
+impl SignalForwarder {
+    // Starts catching the signals right away, before the command is started,
+    // so that none of them can end rcz while it is being spawned.
+    pub fn new() -> io::Result<SignalForwarder> {
+        Ok(SignalForwarder { signals: SignalsInfo::new(FORWARDED)? })
+    }
+
+    // Forwards the signals to the child until the returned handle is closed.
+    // `own_group` is set when the child leads a process group of its own, as
+    // in pty mode, and the signals then go to that whole group. Otherwise the
+    // child shares our process group, so signals from the terminal have
+    // already reached it and only the ones sent to rcz alone are passed on.
+    pub fn start(mut self, child: u32, own_group: bool) -> Handle {
+        let handle = self.signals.handle();
+        let target = if own_group { -(child as libc::pid_t) } else { child as libc::pid_t };
+
+        std::thread::spawn(move || {
+            for origin in self.signals.forever() {
+                let signal = origin.signal;
+                let forward = if own_group {
+                    // The pseudoterminal signals its own window size changes.
+                    (signal != SIGWINCH).then_some(signal).map(|signal| {
+                        // The child's new session leaves its process group
+                        // orphaned, and the kernel ignores the stop signals
+                        // of the terminal in orphaned groups.
+                        if STOPPING.contains(&signal) {
+                            SIGSTOP
+                        } else {
+                            signal
+                        }
+                    })
+                } else {
+                    (origin.cause != Cause::Kernel).then_some(signal)
+                };
+                if let Some(signal) = forward {
+                    unsafe {
+                        libc::kill(target, signal);
+                    }
+                }
+                // Stop along with the child so that the shell sees the job
+                // stop; the SIGCONT of `fg` or `bg` lets us carry on.
+                if STOPPING.contains(&signal) {
+                    let _ = low_level::raise(SIGSTOP);
+                }
+            }
+        });
+
+        handle
+    }
+}
@@ -0,0 +1,54 @@
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rcz-test-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn write_profile(name: &str, profile: &str) -> PathBuf {
+    let profile_path = test_dir(name).join("conf.profile");
+    fs::write(&profile_path, profile).unwrap();
+    profile_path
+}
+
+pub fn write_configs(name: &str, profile: &str) -> PathBuf {
+    let profile_path = write_profile(name, profile);
+    let main_path = profile_path.with_file_name("rcz.conf");
+    fs::write(&main_path, format!("^(echo|sh)\\b={}\n", profile_path.display())).unwrap();
+    main_path
+}
+
+pub fn rcz(main_conf: &Path) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_rcz"));
+    cmd.env_remove("NO_COLOR").env_remove("FORCE_COLOR");
+    cmd.args(["--colour", "on", "-c"]).arg(main_conf);
+    cmd
+}
+
+// rcz with HOME, and so the user's configuration directories, in `home`,
+// and run from there.
+pub fn rcz_in(home: &Path) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_rcz"));
+    cmd.current_dir(home)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("xdg"))
+        .env_remove("RCZ_CONFIG")
+        .env_remove("RCZ_PATH")
+        .args(["--colour", "on"]);
+    cmd
+}
+
+// Runs `input` through rcz with the given profile and returns the output.
+pub fn colorize(name: &str, profile: &str, input: &str) -> String {
+    let main_conf = write_configs(name, profile);
+    let output = rcz(&main_conf)
+        .args(["sh", "-c", "printf '%s' \"$1\"", "sh", input])
+        .output()
+        .unwrap();
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
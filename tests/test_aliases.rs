@@ -0,0 +1,40 @@
+mod common;
+
+use common::test_dir;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+fn aliases(args: &[&str], shell: &str) -> String {
+    let dir = test_dir("aliases");
+    let bin = dir.join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    for command in ["ping", "rcz"] {
+        fs::write(bin.join(command), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(bin.join(command), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    // Not executable, so not a command.
+    fs::write(bin.join("ping6"), "").unwrap();
+    let main_conf = dir.join("rcz.conf");
+    fs::write(&main_conf, "^ping6?\\b=conf.ping\n^rcz\\b=conf.rcz\n^rcz-no-such-command\\b=conf.none\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rcz"))
+        .env("PATH", &bin)
+        .env("SHELL", shell)
+        .arg("-c")
+        .arg(&main_conf)
+        .arg("--aliases")
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).lines().skip(1).collect::<Vec<_>>().join("\n")
+}
+
+#[test]
+fn aliases_wrap_installed_commands_for_each_shell() {
+    assert_eq!(aliases(&[], "/bin/bash"), "alias ping='command rcz ping'");
+    assert_eq!(aliases(&[], "/usr/bin/fish"), "function ping --wraps ping; command rcz ping $argv; end");
+    assert_eq!(aliases(&["zsh"], "/usr/bin/fish"), "alias ping='command rcz ping'");
+    assert_eq!(aliases(&["nushell"], "/bin/bash"), "alias ping = ^rcz ping");
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use common::{colorize, rcz, write_configs};
+
+#[test]
+fn colour_entries_combine_attributes_and_backgrounds() {
+    let profile = "regexp=first\ncolours=bold underline red on_blue\n-\nregexp=second\ncolours=on_bright_green\n-\n";
+    assert_eq!(
+        colorize("colours-attrs", profile, "first second\n"),
+        "\x1b[31;44;1;4mfirst\x1b[39;49;22;24m \x1b[39;102msecond\x1b[39;49m\n"
+    );
+}
+
+#[test]
+fn unknown_and_neutral_tokens_keep_default_colours() {
+    let profile = "regexp=word\ncolours=unchanged nonsense\n-\n";
+    assert_eq!(colorize("colours-neutral", profile, "word\n"), "word\n");
+}
+
+#[test]
+fn inner_groups_override_the_whole_match_colour() {
+    let profile = "regexp=time=(\\d+) ms\ncolours=green,bold yellow\n-\n";
+    assert_eq!(
+        colorize("colours-nested", profile, "time=12 ms\n"),
+        "\x1b[32;49mtime=\x1b[39;49m\x1b[33;49;1m12\x1b[39;49;22m\x1b[32;49m ms\x1b[39;49m\n"
+    );
+}
+
+#[test]
+fn only_the_matched_occurrence_is_coloured() {
+    // The text "1" appears three times, but only the match at the end is coloured.
+    let profile = "regexp=seq (\\d+)$\ncolours=red,bold\n-\n";
+    assert_eq!(
+        colorize("colours-offsets", profile, "host1 ttl=1 seq 1\n"),
+        "host1 ttl=1 \x1b[31;49mseq \x1b[39;49m\x1b[39;49;1m1\x1b[39;49;22m\n"
+    );
+}
+
+#[test]
+fn every_match_on_the_line_is_coloured() {
+    let profile = "regexp=\\d+(ms)\ncolours=cyan,bold\n-\n";
+    assert_eq!(
+        colorize("colours-all", profile, "3ms 4ms\n"),
+        "\x1b[36;49m3\x1b[39;49m\x1b[39;49;1mms\x1b[39;49;22m \x1b[36;49m4\x1b[39;49m\x1b[39;49;1mms\x1b[39;49;22m\n"
+    );
+}
+
+#[test]
+fn colour_entries_go_to_the_group_with_their_index() {
+    // The whole match and group 2 are left alone, group 1 is red and group 3
+    // has no entry at all.
+    let profile = "regexp=(a)(b)(c)\ncolours=unchanged,red,none\n-\n";
+    assert_eq!(colorize("colours-index", profile, "abc\n"), "\x1b[31;49ma\x1b[39;49mbc\n");
+}
+
+#[test]
+fn unchanged_groups_keep_the_colour_around_them() {
+    let profile = "regexp=x(\\d+)y\ncolours=green,unchanged\n-\n";
+    assert_eq!(colorize("colours-unchanged", profile, "x12y\n"), "\x1b[32;49mx12y\x1b[39;49m\n");
+}
+
+#[test]
+fn named_groups_take_the_colour_given_for_their_name() {
+    // The named colour replaces the positional entry for the same group.
+    let profile = "regexp=(?P<ip>\\d+\\.\\d+) (?P<ms>\\d+ ms)\ncolours.ms=bold yellow\ncolours=unchanged,red,green\ncolours.ip=cyan\n-\n";
+    assert_eq!(
+        colorize("colours-named", profile, "10.1 12 ms\n"),
+        "\x1b[36;49m10.1\x1b[39;49m \x1b[33;49;1m12 ms\x1b[39;49;22m\n"
+    );
+}
+
+#[test]
+fn thresholds_pick_the_colour_of_a_number() {
+    let profile = "regexp=time=(\\S+) ms\ncolours=unchanged,blue\nvalues.1=<50 green, <200 yellow, else red\n-\n";
+    assert_eq!(
+        colorize("values-thresholds", profile, "time=12.5 ms\ntime=50 ms\ntime=900 ms\ntime=? ms\n"),
+        "time=\x1b[32;49m12.5\x1b[39;49m ms\n\
+         time=\x1b[33;49m50\x1b[39;49m ms\n\
+         time=\x1b[31;49m900\x1b[39;49m ms\n\
+         time=\x1b[31;49m?\x1b[39;49m ms\n"
+    );
+}
+
+#[test]
+fn value_maps_match_patterns_and_fall_back_to_the_positional_colour() {
+    let profile = "regexp=HTTP (?P<status>\\d+)\ncolours=unchanged,blue\nvalues.status=2xx green, 4xx yellow, 5xx red\n-\n";
+    assert_eq!(
+        colorize("values-map", profile, "HTTP 204\nHTTP 503\nHTTP 302\n"),
+        "HTTP \x1b[32;49m204\x1b[39;49m\n\
+         HTTP \x1b[31;49m503\x1b[39;49m\n\
+         HTTP \x1b[34;49m302\x1b[39;49m\n"
+    );
+}
+
+fn colorize_with_term(name: &str, colours: &str, colorterm: &str, term: &str) -> String {
+    let main_conf = write_configs(name, &format!("regexp=x\ncolours={}\n-\n", colours));
+    let output = rcz(&main_conf)
+        .env("COLORTERM", colorterm)
+        .env("TERM", term)
+        .args(["echo", "x"])
+        .output()
+        .unwrap();
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn extended_colours_are_emitted_on_truecolor_terminals() {
+    let cases = [
+        ("color196", "\x1b[38;5;196;49m"),
+        ("on_color22", "\x1b[39;48;5;22m"),
+        ("#ff8800", "\x1b[38;2;255;136;0;49m"),
+        ("bold on_#202020", "\x1b[39;48;2;32;32;32;1m"),
+        ("rgb(255, 0, 0)", "\x1b[38;2;255;0;0;49m"),
+    ];
+    for (i, (colours, escape)) in cases.iter().enumerate() {
+        let output = colorize_with_term(&format!("ext-{}", i), colours, "truecolor", "xterm-256color");
+        assert!(output.starts_with(&format!("{}x", escape)), "{}: {:?}", colours, output);
+    }
+}
+
+#[test]
+fn extended_colours_are_downsampled_for_the_terminal() {
+    let output = colorize_with_term("ext-256", "#ff0000", "", "xterm-256color");
+    assert!(output.starts_with("\x1b[38;5;196;49mx"), "{:?}", output);
+
+    let output = colorize_with_term("ext-16", "#ff0000 on_color4", "", "xterm");
+    assert!(output.starts_with("\x1b[91;44mx"), "{:?}", output);
+}
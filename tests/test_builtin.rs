@@ -0,0 +1,51 @@
+mod common;
+
+use common::{rcz_in, test_dir};
+use rustcolorizer::builtin::{BUILTIN_FILES, BUILTIN_MAIN_CONFIG};
+use rustcolorizer::{config, load_main_config};
+use std::fs;
+
+#[test]
+fn every_builtin_profile_parses_and_is_reachable() {
+    for name in BUILTIN_FILES.keys().filter(|name| **name != "rcz.conf") {
+        let conf = load_main_config(&format!("builtin:{}", name)).unwrap();
+        assert!(!conf.items.is_empty(), "{} has no rules", name);
+    }
+
+    for entry in config::read_main_config(BUILTIN_MAIN_CONFIG).unwrap() {
+        regex::Regex::new(&entry.pattern).unwrap();
+        assert!(BUILTIN_FILES.contains_key(entry.profile.as_str()), "{} is not built in", entry.profile);
+    }
+}
+
+#[test]
+fn builtin_profiles_are_used_without_configuration() {
+    let home = test_dir("builtin-fallback");
+    fs::write(home.join("a"), "same\nold\n").unwrap();
+    fs::write(home.join("b"), "same\nnew\n").unwrap();
+
+    // RCZ_PATH keeps out the host's system-wide files, such as an installed grc's.
+    let output = rcz_in(&home).env("RCZ_PATH", home.join(".rcz")).args(["diff", "a", "b"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\x1b[36;49m2c2\x1b[39;49m\n\x1b[31;49m< old\x1b[39;49m\n\x1b[31;49m---\x1b[39;49m\n\x1b[32;49m> new\x1b[39;49m\n"
+    );
+}
+
+#[test]
+fn user_profiles_override_builtin_ones() {
+    let home = test_dir("builtin-override");
+    fs::create_dir_all(home.join(".rcz")).unwrap();
+    fs::write(home.join(".rcz/conf.ping"), "regexp=time\ncolours=magenta\n-\n").unwrap();
+
+    let output = rcz_in(&home)
+        .args(["--filter", "-c", "conf.ping"])
+        .arg(home.join(".rcz/conf.ping"))
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "regexp=\x1b[35;49mtime\x1b[39;49m\ncolours=magenta\n-\n"
+    );
+}
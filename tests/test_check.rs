@@ -0,0 +1,130 @@
+mod common;
+
+use common::test_dir;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+// Runs rcz with the arguments and returns its exit code and the problems it
+// printed, with `dir` left out of their locations. Invalid regexes go on to
+// show where in the regexp the error is; only their location is kept.
+fn check(dir: &Path, args: &[&OsStr]) -> (Option<i32>, Vec<String>) {
+    let output = Command::new(env!("CARGO_BIN_EXE_rcz")).args(args).output().unwrap();
+    let prefix = format!("{}/", dir.display());
+    let problems = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.strip_prefix(&prefix))
+        .map(|line| line.split(": invalid regex").next().unwrap_or(line).to_string())
+        .collect();
+    (output.status.code(), problems)
+}
+
+fn check_profile(name: &str, profile: &str) -> (Option<i32>, Vec<String>) {
+    let dir = test_dir(name);
+    let path = dir.join("conf.bad");
+    fs::write(&path, profile).unwrap();
+    check(&dir, &["check".as_ref(), path.as_ref()])
+}
+
+#[test]
+fn main_config_problems_and_the_profiles_they_name() {
+    let dir = test_dir("check-main");
+    let main_conf = dir.join("rcz.conf");
+    fs::write(&main_conf, "^ok\\b=conf.bad\n^(broken=conf.bad\n^gone\\b=conf.missing\n").unwrap();
+    fs::write(dir.join("conf.bad"), "regexp=x\ncolors=red\n").unwrap();
+
+    let (status, problems) = check(&dir, &["-c".as_ref(), main_conf.as_ref(), "check".as_ref()]);
+    assert_eq!(status, Some(1));
+    assert_eq!(
+        problems,
+        vec!["rcz.conf:2", "rcz.conf:3: profile `conf.missing` not found", "conf.bad:2: unknown key `colors`"]
+    );
+}
+
+#[test]
+fn unknown_keys_colours_and_count_modes() {
+    let (status, problems) = check_profile(
+        "check-keys",
+        "# header\n\
+         regexp=(\\d+) ms\n\
+         colours=green,bold,red\n\
+         -\n\
+         regexp=x\n\
+         colours=grene on_blu\n\
+         colors=red\n\
+         count=sometimes\n\
+         -\n\
+         regexp=(unclosed\n",
+    );
+    assert_eq!(status, Some(1));
+    assert_eq!(
+        problems,
+        vec![
+            "conf.bad:3: 3 colours but the regexp only has 2 groups counting the whole match",
+            "conf.bad:6: unknown colour or attribute `grene`",
+            "conf.bad:6: unknown colour or attribute `on_blu`",
+            "conf.bad:7: unknown key `colors`",
+            "conf.bad:8: invalid count mode `sometimes`",
+            "conf.bad:10",
+        ]
+    );
+}
+
+#[test]
+fn problems_are_listed_in_line_order() {
+    // The unreachable rule is only found once the whole profile has been read.
+    let (_, problems) = check_profile(
+        "check-order",
+        "regexp=.*\n\
+         count=stop\n\
+         -\n\
+         regexp=never\n\
+         colours=red\n\
+         -\n\
+         regexp=(?P<a>y)\n\
+         colours.b=red\n\
+         colours=blue\n\
+         count=sometimes\n",
+    );
+    assert_eq!(
+        problems,
+        vec![
+            "conf.bad:4: unreachable: the rule at line 1 matches every line and stops",
+            "conf.bad:8: the regexp has no group named `b`",
+            "conf.bad:10: invalid count mode `sometimes`",
+        ]
+    );
+}
+
+#[test]
+fn named_group_colours() {
+    let (_, problems) = check_profile("check-named", "regexp=(?P<a>y)\ncolours.b=red\ncolours.a=red, blue\n");
+    assert_eq!(
+        problems,
+        vec!["conf.bad:2: the regexp has no group named `b`", "conf.bad:3: `colours.a` takes one colour, not 2"]
+    );
+}
+
+#[test]
+fn value_thresholds() {
+    let (_, problems) = check_profile("check-values", "regexp=(?P<a>y)\nvalues.a=<fast green, 5xx red\n");
+    assert_eq!(problems, vec!["conf.bad:2: invalid threshold `<fast`"]);
+}
+
+#[test]
+fn gradient_stops() {
+    let (_, problems) = check_profile("check-gradient", "regexp=(?P<a>y)\ngradient.a=log, 0 #000000, 10 #ffffff\n");
+    assert_eq!(problems, vec!["conf.bad:2: logarithmic gradient stop `0 #000000` is not above 0"]);
+}
+
+#[test]
+fn check_accepts_clean_files_and_builtins() {
+    let dir = test_dir("check-clean");
+    let profile = dir.join("conf.good");
+    fs::write(&profile, "regexp=(\\d+) ms\ncolours=green,bold\ncount=stop\n-\nregexp=time\ncolours=red\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rcz")).arg("check").arg(&profile).arg("builtin:rcz.conf").output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(output.status.code(), Some(0));
+}
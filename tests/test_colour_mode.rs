@@ -0,0 +1,33 @@
+use rustcolorizer::config::{use_colour, ColourMode};
+use std::env;
+
+// The variables are process wide, so all cases run in this one test.
+#[test]
+fn auto_mode_follows_the_environment_on_a_terminal() {
+    let with_env = |vars: &[(&str, &str)]| {
+        for name in ["NO_COLOR", "FORCE_COLOR", "TERM"] {
+            env::remove_var(name);
+        }
+        for (name, value) in vars {
+            env::set_var(name, value);
+        }
+        use_colour(ColourMode::Auto, true)
+    };
+
+    assert!(with_env(&[("TERM", "xterm")]));
+    assert!(!with_env(&[("TERM", "xterm"), ("NO_COLOR", "1")]));
+    assert!(with_env(&[("TERM", "xterm"), ("NO_COLOR", "")]));
+    assert!(!with_env(&[("TERM", "dumb")]));
+    assert!(with_env(&[("TERM", "xterm"), ("NO_COLOR", "1"), ("FORCE_COLOR", "1")]));
+    assert!(with_env(&[("TERM", "dumb"), ("FORCE_COLOR", "1")]));
+    assert!(!with_env(&[("TERM", "xterm"), ("NO_COLOR", "1"), ("FORCE_COLOR", "0")]));
+
+    // Off a terminal only FORCE_COLOR turns colours on, and on and off
+    // modes do not look at the environment at all.
+    with_env(&[("TERM", "xterm"), ("NO_COLOR", "1")]);
+    assert!(!use_colour(ColourMode::Auto, false));
+    assert!(use_colour(ColourMode::On, false));
+    with_env(&[("FORCE_COLOR", "1")]);
+    assert!(use_colour(ColourMode::Auto, false));
+    assert!(!use_colour(ColourMode::Off, true));
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use common::test_dir;
+use std::fs;
+use std::process::Command;
+
+fn completion(shell: &str, main_conf: &std::path::Path) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_rcz"))
+        .arg("-c")
+        .arg(main_conf)
+        .args(["--completion", shell])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn completions_offer_configured_commands() {
+    let dir = test_dir("completion");
+    let main_conf = dir.join("rcz.conf");
+    fs::write(&main_conf, "^ping6?\\b=conf.ping\n^(gcc|g\\+\\+)(-\\d+)?\\b=conf.gcc\n^tail\\b.*syslog=conf.syslog\n").unwrap();
+
+    let bash = completion("bash", &main_conf);
+    assert!(bash.contains("ping ping6 gcc g++ tail"), "{}", bash);
+    let check = Command::new("bash").args(["-n", "-c", &bash]).status().unwrap();
+    assert!(check.success());
+
+    let zsh = completion("zsh", &main_conf);
+    assert!(zsh.contains("(ping ping6 gcc g++ tail)"), "{}", zsh);
+
+    let fish = completion("fish", &main_conf);
+    assert!(fish.contains("-f -a \"ping ping6 gcc g++ tail\""), "{}", fish);
+
+    let elvish = completion("elvish", &main_conf);
+    assert!(elvish.contains("cand g++ 'command'"), "{}", elvish);
+
+    let powershell = completion("powershell", &main_conf);
+    assert!(powershell.contains("[CompletionResult]::new('ping6', 'ping6', [CompletionResultType]::Command, 'ping6')"));
+}
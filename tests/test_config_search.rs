@@ -0,0 +1,115 @@
+mod common;
+
+use common::{rcz_in, test_dir};
+use std::fs;
+use std::path::Path;
+
+fn write(path: &Path, contents: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+#[test]
+fn main_configs_are_searched_in_order_and_profiles_resolved() {
+    let home = test_dir("search-order");
+    // The XDG config has no rule for echo, so ~/.rcz/rcz.conf is consulted next.
+    // Its profile is not next to it, so it is found in the XDG directory.
+    write(&home.join("xdg/rcz/rcz.conf"), "^ls$=conf.ls\n");
+    write(&home.join(".rcz/rcz.conf"), "^echo\\b=conf.echo\n");
+    write(&home.join("xdg/rcz/conf.echo"), "regexp=xdg\ncolours=green\n-\n");
+
+    let output = rcz_in(&home).args(["echo", "home xdg"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "home \x1b[32;49mxdg\x1b[39;49m\n");
+
+    // A profile next to the main configuration wins over the other directories.
+    write(&home.join(".rcz/conf.echo"), "regexp=home\ncolours=red\n-\n");
+    let output = rcz_in(&home).args(["echo", "home xdg"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\x1b[31;49mhome\x1b[39;49m xdg\n");
+}
+
+#[test]
+fn rcz_config_takes_precedence_over_directories() {
+    let home = test_dir("search-env");
+    write(&home.join(".rcz/rcz.conf"), "^echo\\b=conf.echo\n");
+    write(&home.join(".rcz/conf.echo"), "regexp=word\ncolours=red\n-\n");
+    write(&home.join("custom/main.conf"), "^echo\\b=conf.custom\n");
+    write(&home.join("custom/conf.custom"), "regexp=word\ncolours=blue\n-\n");
+
+    let output = rcz_in(&home).env("RCZ_CONFIG", home.join("custom/main.conf")).args(["echo", "word"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\x1b[34;49mword\x1b[39;49m\n");
+
+    let output = rcz_in(&home).args(["echo", "word"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\x1b[31;49mword\x1b[39;49m\n");
+}
+
+#[test]
+fn rcz_path_replaces_the_search_directories() {
+    let home = test_dir("search-path");
+    write(&home.join(".rcz/rcz.conf"), "^echo\\b=conf.echo\n");
+    write(&home.join(".rcz/conf.echo"), "regexp=word\ncolours=red\n-\n");
+    write(&home.join("first/conf.echo"), "regexp=word\ncolours=blue\n-\n");
+    write(&home.join("second/rcz.conf"), "^echo\\b=conf.echo\n");
+
+    // The profile is next to the main configuration in `second`, but `first` comes before it.
+    let path = std::env::join_paths([home.join("first"), home.join("second")]).unwrap();
+    let output = rcz_in(&home).env("RCZ_PATH", path).args(["echo", "word"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\x1b[34;49mword\x1b[39;49m\n");
+}
+
+#[test]
+fn missing_profiles_are_reported() {
+    let home = test_dir("search-missing");
+    write(&home.join(".rcz/rcz.conf"), "# main\n^echo\\b=conf.nowhere\n");
+
+    let output = rcz_in(&home).args(["echo", "word"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("rcz.conf:2: profile `conf.nowhere` not found"));
+}
+
+#[test]
+fn grc_style_main_configs_are_understood() {
+    let home = test_dir("search-grc");
+    write(&home.join("grc.conf"), "# echo\n(^|/)echo\\s?\nconf.echo\n\n# ls\n^ls\nconf.ls\n");
+    write(&home.join("conf.echo"), "regexp=word\ncolours=yellow\n-\n");
+
+    let output = rcz_in(&home).arg("-c").arg(home.join("grc.conf")).args(["echo", "word"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\x1b[33;49mword\x1b[39;49m\n");
+}
+
+#[test]
+fn patterns_may_contain_equals_signs() {
+    let home = test_dir("search-equals");
+    write(&home.join(".rcz/rcz.conf"), "^ls\\s+--x=y\\b=conf.ls\n^echo\\b=conf.echo\n");
+    write(&home.join(".rcz/conf.ls"), "regexp=word\ncolours=red\n-\n");
+    write(&home.join(".rcz/conf.echo"), "regexp=word\ncolours=yellow\n-\n");
+
+    let output = rcz_in(&home).args(["echo", "word"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\x1b[33;49mword\x1b[39;49m\n");
+
+    let output = rcz_in(&home).arg("check").arg(home.join(".rcz/rcz.conf")).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert!(output.status.success());
+}
+
+#[test]
+fn patterns_match_the_whole_command_line() {
+    let home = test_dir("command-line");
+    // The first matching entry wins, so the more specific pattern comes first.
+    write(
+        &home.join(".rcz/rcz.conf"),
+        "^sh -c echo\\b=conf.shecho\n^sh\\b=conf.sh\n^echo word$=conf.echo\n",
+    );
+    write(&home.join(".rcz/conf.shecho"), "regexp=word\ncolours=green\n-\n");
+    write(&home.join(".rcz/conf.sh"), "regexp=word\ncolours=red\n-\n");
+    write(&home.join(".rcz/conf.echo"), "regexp=word\ncolours=yellow\n-\n");
+
+    let output = rcz_in(&home).args(["sh", "-c", "echo word"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\x1b[32;49mword\x1b[39;49m\n");
+
+    let output = rcz_in(&home).args(["sh", "-c", "printf 'word\\n'"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\x1b[31;49mword\x1b[39;49m\n");
+
+    // A command given by path also matches on its basename.
+    let output = rcz_in(&home).args(["/bin/echo", "word"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\x1b[33;49mword\x1b[39;49m\n");
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use common::colorize;
+
+const RED: &str = "\x1b[31;49m";
+const GREEN: &str = "\x1b[32;49m";
+const CLEAR: &str = "\x1b[39;49m";
+
+#[test]
+fn once_and_more_colour_first_or_all_matches() {
+    let once = colorize("count-once", "regexp=a\\d\ncolours=red\ncount=once\n-\n", "a1 a2\n");
+    assert_eq!(once, format!("{RED}a1{CLEAR} a2\n"));
+
+    let more = colorize("count-more", "regexp=a\\d\ncolours=red\ncount=more\n-\n", "a1 a2\n");
+    assert_eq!(more, format!("{RED}a1{CLEAR} {RED}a2{CLEAR}\n"));
+}
+
+#[test]
+fn stop_skips_the_remaining_rules() {
+    let profile = "regexp=a\ncolours=red\ncount=stop\n-\nregexp=b\ncolours=green\n-\n";
+    assert_eq!(colorize("count-stop", profile, "a b\nb\n"), format!("{RED}a{CLEAR} b\n{GREEN}b{CLEAR}\n"));
+}
+
+#[test]
+fn previous_reuses_the_previous_rule() {
+    let profile = "regexp=a\\d\ncolours=green\ncount=once\n-\nregexp=b\\d\ncount=previous\n-\n";
+    assert_eq!(
+        colorize("count-previous", profile, "a1 b1 b2\n"),
+        format!("{GREEN}a1{CLEAR} {GREEN}b1{CLEAR} b2\n")
+    );
+}
+
+#[test]
+fn block_colours_every_line_until_unblock() {
+    let profile = "regexp=^BEGIN\ncolours=red\ncount=block\n-\nregexp=^END\ncount=unblock\n-\n";
+    assert_eq!(
+        colorize("count-block", profile, "before\nBEGIN\ninside\nEND\nafter\n"),
+        format!("before\n{RED}BEGIN{CLEAR}\n{RED}inside{CLEAR}\n{RED}END{CLEAR}\nafter\n")
+    );
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use common::{rcz, test_dir, write_configs};
+use std::fs;
+
+const PROFILE: &str = "regexp=(\\d+) ms\ncolours=green,bold\ncount=stop\n-\nregexp=time\ncolours=red\n-\n";
+
+#[test]
+fn debug_explains_config_choice_and_rule_matches() {
+    let main_conf = write_configs("debug", PROFILE);
+    let log = test_dir("debug").join("debug.log");
+    let output = rcz(&main_conf)
+        .env_remove("RUST_LOG")
+        .arg(format!("--debug={}", log.display()))
+        .args(["echo", "time 12 ms"])
+        .output()
+        .unwrap();
+
+    // The trace goes to the file, leaving the output as it would be.
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "time \x1b[39;49;1m12\x1b[39;49;22m\x1b[32;49m ms\x1b[39;49m\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+
+    let log = fs::read_to_string(log).unwrap();
+    assert!(log.contains(&format!("{}:1: `^(echo|sh)\\b` matches `echo time 12 ms`", main_conf.display())), "{}", log);
+    assert!(log.contains("rule 1 /(\\d+) ms/: matched 0:5..10 1:5..7"), "{}", log);
+    assert!(log.contains("rule 1 has count=stop, so no further rules are tried"), "{}", log);
+    assert!(!log.contains("rule 2"), "{}", log);
+}
+
+#[test]
+fn debug_output_defaults_to_stderr() {
+    let main_conf = write_configs("debug-stderr", PROFILE);
+    let output = rcz(&main_conf).env_remove("RUST_LOG").args(["--debug", "echo", "nothing"]).output().unwrap();
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "nothing\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("rule 2 /time/: no match"), "{}", stderr);
+}
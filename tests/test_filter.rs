@@ -0,0 +1,51 @@
+mod common;
+
+use common::{test_dir, write_profile};
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn rcz_filter(profile: &std::path::Path) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_rcz"));
+    cmd.args(["--colour", "on", "--filter", "-c"]).arg(profile);
+    cmd
+}
+
+#[test]
+fn filter_colorizes_stdin() {
+    let profile = write_profile("filter-stdin", "regexp=\\d+ ms\ncolours=green\n-\n");
+    let mut child = rcz_filter(&profile).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(b"64 bytes: time=12 ms\nno match\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "64 bytes: time=\x1b[32;49m12 ms\x1b[39;49m\nno match\n"
+    );
+}
+
+#[test]
+fn filter_passes_lines_that_are_not_utf8_through() {
+    let profile = write_profile("filter-latin1", "regexp=caf\ncolours=green\n-\n");
+    let mut child = rcz_filter(&profile).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(b"caf\xe9\ncafe\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.stdout, b"caf\xe9\n\x1b[32;49mcaf\x1b[39;49me\n");
+}
+
+#[test]
+fn filter_reads_files_in_order() {
+    let profile = write_profile("filter-files", "regexp=ERROR\ncolours=red\n-\n");
+    let dir = test_dir("filter-files");
+    fs::write(dir.join("a.log"), "ERROR one\n").unwrap();
+    fs::write(dir.join("b.log"), "ok two\n").unwrap();
+    let output = rcz_filter(&profile).arg(dir.join("a.log")).arg(dir.join("b.log")).output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\x1b[31;49mERROR\x1b[39;49m one\nok two\n");
+
+    let output = rcz_filter(&profile).arg(dir.join("missing.log")).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use common::{rcz, write_configs};
+
+// Colours `input` with a one rule profile using the given gradient, as a
+// terminal of the given colour depth would get it.
+fn colorize_gradient(name: &str, gradient: &str, input: &str, colorterm: &str, term: &str) -> String {
+    let profile = format!("regexp=v=(\\S+)\ncolours=unchanged,blue\ngradient.1={}\n-\n", gradient);
+    let output = rcz(&write_configs(name, &profile))
+        .env("COLORTERM", colorterm)
+        .env("TERM", term)
+        .args(["sh", "-c", "printf '%s' \"$1\"", "sh", input])
+        .output()
+        .unwrap();
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn values_are_placed_linearly_between_the_stops() {
+    let output = colorize_gradient(
+        "gradient-linear",
+        "0 #00ff00, 100 #ff0000",
+        "v=50\nv=150\nv=-5\nv=?\n",
+        "truecolor",
+        "xterm-256color",
+    );
+    assert_eq!(
+        output,
+        "v=\x1b[38;2;128;128;0;49m50\x1b[39;49m\n\
+         v=\x1b[38;2;255;0;0;49m150\x1b[39;49m\n\
+         v=\x1b[38;2;0;255;0;49m-5\x1b[39;49m\n\
+         v=\x1b[34;49m?\x1b[39;49m\n"
+    );
+}
+
+#[test]
+fn log_gradients_and_background_stops() {
+    let output = colorize_gradient("gradient-log", "log, 1 #000000, 100 #ffffff", "v=10ms\n", "truecolor", "xterm");
+    assert_eq!(output, "v=\x1b[38;2;128;128;128;49m10ms\x1b[39;49m\n");
+
+    let output = colorize_gradient("gradient-back", "0 on_#000000, 10 on_#0000ff", "v=5\n", "truecolor", "xterm");
+    assert_eq!(output, "v=\x1b[39;48;2;0;0;128m5\x1b[39;49m\n");
+}
+
+#[test]
+fn gradients_degrade_to_the_terminal_colours() {
+    let output = colorize_gradient("gradient-256", "0 #00ff00, 100 #ff0000", "v=100\n", "", "xterm-256color");
+    assert_eq!(output, "v=\x1b[38;5;196;49m100\x1b[39;49m\n");
+
+    let output = colorize_gradient("gradient-16", "0 #00ff00, 100 #ff0000", "v=100\n", "", "xterm");
+    assert_eq!(output, "v=\x1b[91;49m100\x1b[39;49m\n");
+}
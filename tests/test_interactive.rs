@@ -0,0 +1,46 @@
+mod common;
+
+use common::test_dir;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn interactive(args: &[&str], input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rcz"))
+        .args(["--colour", "on", "--interactive"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn interactive_mode_previews_and_saves_rules() {
+    let profile = test_dir("interactive").join("conf.new");
+    let _ = fs::remove_file(&profile);
+    let commands = format!("r \\d+ ms\nc green\na\nr bytes\nc red\na 1\nm 1 2\nd 9\np\nw {}\nq\n", profile.display());
+    let output = interactive(&["sh", "-c", "echo '64 bytes time=12 ms'; echo other"], &commands);
+
+    // The rule being written is previewed on the lines it matches.
+    assert!(output.contains("64 bytes time=\x1b[32;49m12 ms\x1b[39;49m\n1 of 2 sample lines match"), "{}", output);
+    assert!(output.contains("error: expected a rule number from 1 to 2"), "{}", output);
+    // The whole sample is previewed with all the rules, in their new order.
+    assert!(output.contains("64 \x1b[31;49mbytes\x1b[39;49m time=\x1b[32;49m12 ms\x1b[39;49m\nother\n"), "{}", output);
+    assert_eq!(
+        fs::read_to_string(&profile).unwrap(),
+        "regexp=\\d+ ms\ncolours=green\n-\nregexp=bytes\ncolours=red\n-\n"
+    );
+
+    // Editing an existing profile starts from its rules.
+    let output = interactive(&["-c", profile.to_str().unwrap(), "sh", "-c", "echo x"], "e 1\nc yellow\na\nw\n");
+    assert!(output.contains("editing: regexp=\\d+ ms  colours=green"), "{}", output);
+    assert_eq!(
+        fs::read_to_string(&profile).unwrap(),
+        "regexp=bytes\ncolours=red\n-\nregexp=\\d+ ms\ncolours=yellow\n-\n"
+    );
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use rustcolorizer::{config, load_main_config, rczat, Colorizer, RczError};
+
+#[test]
+fn colorizer_keeps_block_state_between_lines() {
+    let conf = "regexp=^BEGIN\ncolours=red\ncount=block\n-\nregexp=^END\ncount=unblock\n-\n";
+    let mut colorizer: Colorizer = conf.parse().unwrap();
+
+    assert_eq!(colorizer.colorize("BEGIN"), "\x1b[31;49mBEGIN\x1b[39;49m");
+    assert_eq!(colorizer.colorize("inside"), "\x1b[31;49minside\x1b[39;49m");
+    assert_eq!(colorizer.colorize("END"), "\x1b[31;49mEND\x1b[39;49m");
+    assert_eq!(colorizer.colorize("after"), "after");
+    assert_eq!(colorizer.conf().items.len(), 2);
+}
+
+#[test]
+fn invalid_configs_return_errors() {
+    let err = "regexp=ok\n-\nregexp=(\n".parse::<Colorizer>().err().unwrap();
+    assert!(matches!(err, RczError::Regex { line: 3, .. }));
+
+    let err = "regexp=(?P<ms>\\d+)\ncolours.ns=red\n".parse::<Colorizer>().err().unwrap();
+    assert_eq!(err.to_string(), "<string>:2: the regexp has no group named `ns`");
+
+    let err = "regexp=(?P<ms>\\d+)\ncolours.ms=bold yellow, red\n".parse::<Colorizer>().err().unwrap();
+    assert_eq!(err.to_string(), "<string>:2: `colours.ms` takes one colour, not 2");
+
+    let err = Colorizer::from_file("/nonexistent/rcz.conf").err().unwrap();
+    assert!(matches!(err, RczError::Io { .. }));
+}
+
+#[test]
+fn profiles_are_found_and_streams_colorized_without_the_cli() {
+    let main_conf = common::write_configs("library-stream", "regexp=\\d+ ms\ncolours=green\n-\n");
+    let args = ["echo".to_string(), "hi".to_string()];
+    let profile = config::find_config_file(Some(&main_conf), &args).unwrap();
+    assert_eq!(profile, main_conf.with_file_name("conf.profile").to_string_lossy());
+
+    let conf = load_main_config(&profile).unwrap();
+    let mut output = Vec::new();
+    rczat::colorize_stream(&b"time 12 ms\nnone\n"[..], &mut output, &conf).unwrap();
+    assert_eq!(output, b"time \x1b[32;49m12 ms\x1b[39;49m\nnone\n");
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::{rcz, write_configs, write_profile};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const TRACE_PROFILE: &str = "regexp=Error: .*\\n\\s+at .*\ncolours=red\nlines=3\n-\nregexp=\\d+\ncolours=green\n-\n";
+
+fn filter(profile: &std::path::Path, flags: &[&str], input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rcz"))
+        .args(["--colour", "on", "--filter"])
+        .args(flags)
+        .arg("-c")
+        .arg(profile)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    String::from_utf8_lossy(&child.wait_with_output().unwrap().stdout).into_owned()
+}
+
+#[test]
+fn multiline_rules_match_across_lines() {
+    let profile = write_profile("multiline", TRACE_PROFILE);
+    let input = "x 1\nError: boom\n  at foo 2\nend 3";
+
+    assert_eq!(
+        filter(&profile, &["--multiline"], input),
+        "x \x1b[32;49m1\x1b[39;49m\n\x1b[31;49mError: boom\x1b[39;49m\n\
+         \x1b[31;49m  at foo \x1b[39;49m\x1b[32;49m2\x1b[39;49m\nend \x1b[32;49m3\x1b[39;49m"
+    );
+    // Without --multiline each line is matched on its own.
+    assert_eq!(
+        filter(&profile, &[], input),
+        "x \x1b[32;49m1\x1b[39;49m\nError: boom\n  at foo \x1b[32;49m2\x1b[39;49m\nend \x1b[32;49m3\x1b[39;49m"
+    );
+}
+
+#[test]
+fn multiline_mode_flushes_when_output_pauses() {
+    let main_conf = write_configs("multiline-flush", TRACE_PROFILE);
+    let mut child = rcz(&main_conf)
+        .args(["--multiline", "sh", "-c", "echo 'first 1'; sleep 3; echo second"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let start = Instant::now();
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap()).read_line(&mut line).unwrap();
+
+    assert_eq!(line, "first \x1b[32;49m1\x1b[39;49m\n");
+    assert!(start.elapsed() < Duration::from_secs(2));
+    child.wait().unwrap();
+}
+
+#[test]
+fn rule_line_counts_are_bounded() {
+    let main_conf = write_configs("multiline-bound", "regexp=a\nlines=500\n-\n");
+    let output = rcz(&main_conf).args(["echo", "a"]).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(":2: `lines` must be from 1 to 50, not `500`"));
+}
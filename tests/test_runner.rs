@@ -0,0 +1,227 @@
+mod common;
+
+use common::{rcz, write_configs};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+#[test]
+fn child_stdout_is_colorized() {
+    let main_conf = write_configs("stdout", "regexp=\\d+ ms\ncolours=green\n-\n");
+    let output = rcz(&main_conf)
+        .args(["echo", "time 12 ms"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "time \x1b[32;49m12 ms\x1b[39;49m\n"
+    );
+}
+
+#[test]
+fn commands_without_a_profile_keep_our_stdout() {
+    let main_conf = write_configs("no-profile", "regexp=pipe\ncolours=green\n-\n");
+    let out_path = main_conf.with_file_name("out");
+    // readlink has no profile and sees the file itself; sh, which has one,
+    // writes to the pipe rcz reads from.
+    let run = |args: &[&str]| {
+        let status = rcz(&main_conf)
+            .args(args)
+            .stdout(File::create(&out_path).unwrap())
+            .status()
+            .unwrap();
+        assert!(status.success());
+        fs::read_to_string(&out_path).unwrap()
+    };
+
+    assert_eq!(run(&["readlink", "/proc/self/fd/1"]), format!("{}\n", out_path.display()));
+    assert!(run(&["sh", "-c", "readlink /proc/self/fd/1"]).starts_with("\x1b[32;49mpipe\x1b[39;49m:"));
+}
+
+#[test]
+fn lines_that_are_not_utf8_pass_through_unchanged() {
+    let main_conf = write_configs("latin1", "regexp=\\d+ ms\ncolours=green\n-\n");
+    for flags in [&[][..], &["--multiline"][..]] {
+        let output = rcz(&main_conf)
+            .args(flags)
+            .args(["sh", "-c", "printf 'caf\\351 12 ms\\n1 ms\\n\\377'"])
+            .output()
+            .unwrap();
+        assert_eq!(output.stdout, b"caf\xe9 12 ms\n\x1b[32;49m1 ms\x1b[39;49m\n\xff", "{:?}", flags);
+    }
+}
+
+#[test]
+fn exit_status_is_propagated() {
+    let main_conf = write_configs("status", "regexp=never\ncolours=red\n-\n");
+    let output = rcz(&main_conf)
+        .args(["sh", "-c", "echo plain; exit 3"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "plain\n");
+}
+
+#[test]
+fn stderr_and_stdout_are_colorized_separately() {
+    let main_conf = write_configs("streams", "regexp=err|out\ncolours=red\n-\n");
+    let run = |flags: &[&str]| {
+        rcz(&main_conf)
+            .args(flags)
+            .args(["sh", "-c", "echo out; echo err >&2"])
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&[]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\x1b[31;49mout\x1b[39;49m\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "err\n");
+
+    let output = run(&["-e"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "out\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "\x1b[31;49merr\x1b[39;49m\n");
+
+    let output = run(&["-e", "-s"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\x1b[31;49mout\x1b[39;49m\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "\x1b[31;49merr\x1b[39;49m\n");
+}
+
+#[test]
+fn pty_mode_gives_the_child_a_terminal() {
+    let main_conf = write_configs("pty", "regexp=^tty$\ncolours=green\n-\n");
+    let script = "if [ -t 1 ]; then echo tty; else echo notty; fi";
+    let run = |flags: &[&str]| {
+        rcz(&main_conf)
+            .args(flags)
+            .args(["sh", "-c", script])
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&[]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "notty\n");
+
+    let output = run(&["--pty"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\x1b[32;49mtty\x1b[39;49m\r\n");
+
+    // With -e stderr goes to the terminal too, and so comes out coloured on our stdout.
+    let output = rcz(&main_conf)
+        .args(["--pty", "-e", "sh", "-c", "if [ -t 2 ]; then echo tty >&2; fi"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\x1b[32;49mtty\x1b[39;49m\r\n");
+}
+
+#[test]
+fn pty_mode_passes_our_input_to_the_terminal() {
+    let main_conf = write_configs("pty-input", "regexp=^got\ncolours=green\n-\n");
+    let mut child = rcz(&main_conf)
+        .args(["--pty", "sh", "-c", "read x </dev/tty; echo got $x; read y; echo end $?"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"hi\n").unwrap();
+
+    // Waits for at most five seconds, as a child that never gets its input
+    // would otherwise hang the test.
+    let start = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if start.elapsed() > Duration::from_secs(5) {
+            child.kill().unwrap();
+            panic!("the command did not get its input");
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let mut output = String::new();
+    child.stdout.take().unwrap().read_to_string(&mut output).unwrap();
+    assert!(output.contains("\x1b[32;49mgot\x1b[39;49m hi\r\n"), "{:?}", output);
+    assert!(output.ends_with("end 1\r\n"), "{:?}", output);
+}
+
+#[test]
+fn colour_mode_controls_escape_sequences() {
+    let main_conf = write_configs("colour", "regexp=\\d+\ncolours=green\n-\n");
+    let run = |colour: &str, env: &[(&str, &str)]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rcz"))
+            .env_remove("NO_COLOR")
+            .env_remove("FORCE_COLOR")
+            .envs(env.iter().copied())
+            .args(["--colour", colour, "-c"])
+            .arg(&main_conf)
+            .args(["echo", "42"])
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    let colored = "\x1b[32;49m42\x1b[39;49m\n";
+
+    assert_eq!(run("on", &[]), colored);
+    assert_eq!(run("on", &[("NO_COLOR", "1")]), colored);
+    assert_eq!(run("off", &[("FORCE_COLOR", "1")]), "42\n");
+    // Our stdout is a pipe here, so auto only colorizes when forced.
+    assert_eq!(run("auto", &[]), "42\n");
+    assert_eq!(run("auto", &[("FORCE_COLOR", "1")]), colored);
+    assert_eq!(run("auto", &[("FORCE_COLOR", "0")]), "42\n");
+}
+
+#[test]
+fn config_errors_report_file_and_line() {
+    let main_conf = write_configs("bad-regex", "# comment\nregexp=ok\ncolours=red\n-\nregexp=(unclosed\n");
+    let output = rcz(&main_conf).args(["echo", "hi"]).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let profile = main_conf.with_file_name("conf.profile");
+    assert!(stderr.starts_with(&format!("rcz: {}:5: invalid regex", profile.display())), "{}", stderr);
+}
+
+#[test]
+fn spawn_failures_are_reported() {
+    let main_conf = write_configs("spawn", "regexp=x\ncolours=red\n-\n");
+    let output = rcz(&main_conf).arg("rcz-no-such-command").output().unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("rcz: failed to start rcz-no-such-command"));
+}
+
+#[test]
+fn partial_lines_are_written_without_waiting_for_a_newline() {
+    let main_conf = write_configs("partial", "regexp=\\w+>\ncolours=green\n-\n");
+    let mut child = rcz(&main_conf)
+        .args(["sh", "-c", "printf 'prompt> '; sleep 2; echo done"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let start = Instant::now();
+    let expected = b"\x1b[32;49mprompt>\x1b[39;49m ";
+    let mut prompt = vec![0; expected.len()];
+    child.stdout.as_mut().unwrap().read_exact(&mut prompt).unwrap();
+
+    assert_eq!(prompt, expected);
+    assert!(start.elapsed() < Duration::from_millis(1500));
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "done\n");
+}
+
+#[test]
+fn background_processes_do_not_keep_rcz_running() {
+    let main_conf = write_configs("background", "regexp=hi\ncolours=green\n-\n");
+    let start = Instant::now();
+    // The sleep also holds on to the stderr it shares with rcz.
+    let output = rcz(&main_conf)
+        .args(["sh", "-c", "sleep 5 & echo hi"])
+        .stderr(Stdio::null())
+        .output()
+        .unwrap();
+
+    assert!(start.elapsed() < Duration::from_secs(3));
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\x1b[32;49mhi\x1b[39;49m\n");
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::{rcz, write_configs};
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+// Waits for the child to report that it is running, and returns its output
+// reader for later lines.
+fn wait_ready(child: &mut Child) -> BufReader<std::process::ChildStdout> {
+    let mut reader = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.contains("ready"), "{}", line);
+    reader
+}
+
+fn kill(signal: &str, pid: u32) {
+    assert!(Command::new("kill").args([signal, &pid.to_string()]).status().unwrap().success());
+}
+
+// The state letter of a process in /proc, such as R, S or T for stopped.
+fn process_state(pid: u32) -> char {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap();
+    stat.rsplit(") ").next().unwrap().chars().next().unwrap()
+}
+
+fn wait_for_state(pid: u32, state: char) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(5) {
+        if process_state(pid) == state {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    false
+}
+
+const TRAP_SCRIPT: &str = "trap 'echo got $0; exit 7' TERM INT; echo ready; while :; do sleep 0.05; done";
+
+#[test]
+fn signals_are_forwarded_and_exit_status_kept() {
+    let main_conf = write_configs("signals", "regexp=got\ncolours=red\n-\n");
+    for (flags, signal, name) in [(&[][..], "-TERM", "term"), (&["--pty"][..], "-INT", "int")] {
+        let mut child = rcz(&main_conf)
+            .args(flags)
+            .args(["sh", "-c", TRAP_SCRIPT, name])
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut reader = wait_ready(&mut child);
+        kill(signal, child.id());
+
+        let mut rest = String::new();
+        reader.read_line(&mut rest).unwrap();
+        assert!(rest.starts_with(&format!("\x1b[31;49mgot\x1b[39;49m {}", name)), "{}", rest);
+        assert_eq!(child.wait().unwrap().code(), Some(7));
+    }
+}
+
+#[test]
+fn death_by_signal_exits_with_128_plus_signal() {
+    let main_conf = write_configs("signal-exit", "regexp=x\ncolours=red\n-\n");
+    let output = rcz(&main_conf).args(["sh", "-c", "kill -TERM $$"]).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(128 + 15));
+}
+
+#[test]
+fn stopping_rcz_stops_the_command_until_continued() {
+    let main_conf = write_configs("signal-stop", "regexp=x\ncolours=red\n-\n");
+    // In pty mode the command runs in a session of its own.
+    for flags in [&[][..], &["--pty"][..]] {
+        let mut child = rcz(&main_conf)
+            .args(flags)
+            .args(["sh", "-c", "echo ready; echo $$; read line; echo $line"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut reader = wait_ready(&mut child);
+        let mut pid = String::new();
+        reader.read_line(&mut pid).unwrap();
+        let command_pid: u32 = pid.trim().parse().unwrap();
+
+        kill("-TSTP", child.id());
+        assert!(wait_for_state(child.id(), 'T'), "{:?}", flags);
+        assert!(wait_for_state(command_pid, 'T'), "{:?}", flags);
+
+        kill("-CONT", child.id());
+        assert!(wait_for_state(command_pid, 'S'), "{:?}", flags);
+        drop(child.stdin.take());
+        assert_eq!(child.wait().unwrap().code(), Some(0), "{:?}", flags);
+    }
+}